@@ -0,0 +1,5 @@
+mod obsolete_file_collector;
+#[cfg(test)]
+mod tests;
+
+pub use obsolete_file_collector::{LogPin, ObsoleteFileCollector};
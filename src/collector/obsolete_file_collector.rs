@@ -0,0 +1,191 @@
+use crate::error::Result;
+use crate::logger::{LogManager, LogNumber};
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{spawn, JoinHandle};
+
+enum CollectorTask {
+    Collect(HashSet<LogNumber>),
+    Retry,
+    Wait(Sender<()>),
+    Stop,
+}
+
+/// Deletes log files that are neither referenced by a live memtable nor pinned by a reader.
+/// Table files do not exist yet, logs are the only files it collects.
+pub struct ObsoleteFileCollector {
+    sender: Mutex<Sender<CollectorTask>>,
+    pinned_logs: Arc<Mutex<HashMap<LogNumber, usize>>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+/// Keeps a log file from being collected until it is dropped.
+pub struct LogPin {
+    log_number: LogNumber,
+    sender: Sender<CollectorTask>,
+    pinned_logs: Arc<Mutex<HashMap<LogNumber, usize>>>,
+}
+
+#[allow(dead_code)]
+impl ObsoleteFileCollector {
+    pub fn new(log_manager: Arc<LogManager>) -> Self {
+        let (sender, receiver) = channel();
+        let pinned_logs = Arc::new(Mutex::new(HashMap::new()));
+        let pinned_logs_ref = pinned_logs.clone();
+
+        ObsoleteFileCollector {
+            sender: Mutex::new(sender),
+            pinned_logs,
+            handle: Some(spawn(move || {
+                Self::run(log_manager, receiver, pinned_logs_ref)
+            })),
+        }
+    }
+
+    /// Schedules the deletion of every log file not contained by `live_logs`.
+    pub fn collect(&self, live_logs: HashSet<LogNumber>) {
+        self.send(CollectorTask::Collect(live_logs));
+    }
+
+    /// Blocks until every scheduled collection is finished.
+    pub fn wait(&self) {
+        let (sender, receiver) = channel();
+        self.send(CollectorTask::Wait(sender));
+        receiver.recv().ok();
+    }
+
+    pub fn pin_log(&self, log_number: LogNumber) -> LogPin {
+        *self
+            .pinned_logs
+            .lock()
+            .unwrap()
+            .entry(log_number)
+            .or_insert(0) += 1;
+
+        LogPin {
+            log_number,
+            sender: self.sender.lock().unwrap().clone(),
+            pinned_logs: self.pinned_logs.clone(),
+        }
+    }
+
+    /// Pins the logs `list` returns. The collections hold the pins for their whole run, so a log
+    /// is either deleted before it is listed or pinned before it can be deleted.
+    pub fn pin_logs<F>(&self, list: F) -> Result<Vec<LogPin>>
+    where
+        F: FnOnce() -> Result<Vec<LogNumber>>,
+    {
+        let mut pinned_logs = self.pinned_logs.lock().unwrap();
+        let sender = self.sender.lock().unwrap().clone();
+
+        Ok(list()?
+            .into_iter()
+            .map(|log_number| {
+                *pinned_logs.entry(log_number).or_insert(0) += 1;
+
+                LogPin {
+                    log_number,
+                    sender: sender.clone(),
+                    pinned_logs: self.pinned_logs.clone(),
+                }
+            })
+            .collect())
+    }
+
+    /// Deletes the obsolete logs immediately, used on open to remove files left by a crash.
+    pub fn collect_orphans(
+        log_manager: &LogManager,
+        live_logs: &HashSet<LogNumber>,
+    ) -> Result<Vec<LogNumber>> {
+        Self::collect_logs(log_manager, live_logs, &HashMap::new())
+    }
+
+    fn send(&self, task: CollectorTask) {
+        self.sender.lock().unwrap().send(task).ok();
+    }
+
+    fn collect_logs(
+        log_manager: &LogManager,
+        live_logs: &HashSet<LogNumber>,
+        pinned_logs: &HashMap<LogNumber, usize>,
+    ) -> Result<Vec<LogNumber>> {
+        let obsolete_logs: Vec<LogNumber> = log_manager
+            .get_exist_log_number()?
+            .into_iter()
+            .filter(|n| !live_logs.contains(n) && !pinned_logs.contains_key(n))
+            .collect();
+
+        for log_number in obsolete_logs.iter() {
+            log_manager.truncate_log(*log_number)?;
+        }
+
+        log_manager.purge_archive(pinned_logs)?;
+
+        Ok(obsolete_logs)
+    }
+
+    fn run(
+        log_manager: Arc<LogManager>,
+        receiver: Receiver<CollectorTask>,
+        pinned_logs: Arc<Mutex<HashMap<LogNumber, usize>>>,
+    ) {
+        let mut live_logs = None;
+
+        while let Ok(task) = receiver.recv() {
+            match task {
+                CollectorTask::Collect(logs) => live_logs = Some(logs),
+                CollectorTask::Retry => (),
+                CollectorTask::Wait(sender) => {
+                    sender.send(()).ok();
+                    continue;
+                }
+                CollectorTask::Stop => break,
+            };
+
+            if let Some(logs) = live_logs.as_ref() {
+                let pinned = pinned_logs.lock().unwrap();
+                // a failed deletion is retried by the next collection
+                Self::collect_logs(log_manager.as_ref(), logs, &pinned).ok();
+            }
+        }
+    }
+}
+
+impl Drop for ObsoleteFileCollector {
+    fn drop(&mut self) {
+        self.send(CollectorTask::Stop);
+
+        if let Some(handle) = self.handle.take() {
+            handle.join().ok();
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl LogPin {
+    pub fn log_number(&self) -> LogNumber {
+        self.log_number
+    }
+}
+
+impl Drop for LogPin {
+    fn drop(&mut self) {
+        let mut pinned_logs = self.pinned_logs.lock().unwrap();
+
+        let released = match pinned_logs.get_mut(&self.log_number) {
+            Some(count) if *count > 1 => {
+                *count -= 1;
+                false
+            }
+            _ => {
+                pinned_logs.remove(&self.log_number);
+                true
+            }
+        };
+
+        if released {
+            self.sender.send(CollectorTask::Retry).ok();
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use crate::collector::ObsoleteFileCollector;
+use crate::logger::LogManager;
+use rand::random;
+use std::collections::HashSet;
+use std::env::temp_dir;
+use std::fs::create_dir;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn create_log_manager() -> Arc<LogManager> {
+    let mut tmp_dir = temp_dir();
+    tmp_dir.push(format!("komachi_test_collector_{}", random::<u16>()));
+    create_dir(tmp_dir.clone()).unwrap();
+
+    Arc::new(LogManager::new(tmp_dir, 0, 4 * 1024, "test").unwrap())
+}
+
+#[test]
+fn test_collect_logs() {
+    let log_manager = create_log_manager();
+
+    for log_number in 1..5 {
+        log_manager.freeze_current_file(log_number).unwrap();
+    }
+
+    let collector = ObsoleteFileCollector::new(log_manager.clone());
+    let pin = collector.pin_log(1);
+    let another_pin = collector.pin_log(1);
+
+    collector.collect([3].iter().cloned().collect::<HashSet<_>>());
+    collector.wait();
+
+    let mut exist_logs = log_manager.get_exist_log_number().unwrap();
+    exist_logs.sort_unstable();
+    assert_eq!(exist_logs, vec![1, 3]);
+
+    drop(pin);
+    collector.wait();
+    let mut exist_logs = log_manager.get_exist_log_number().unwrap();
+    exist_logs.sort_unstable();
+    assert_eq!(exist_logs, vec![1, 3]);
+
+    drop(another_pin);
+    collector.wait();
+    assert_eq!(log_manager.get_exist_log_number().unwrap(), vec![3]);
+}
+
+#[test]
+fn test_collect_orphans() {
+    let log_manager = create_log_manager();
+
+    for log_number in 1..5 {
+        log_manager.freeze_current_file(log_number).unwrap();
+    }
+
+    let mut removed = ObsoleteFileCollector::collect_orphans(
+        log_manager.as_ref(),
+        &[2].iter().cloned().collect::<HashSet<_>>(),
+    )
+    .unwrap();
+    removed.sort_unstable();
+
+    assert_eq!(removed, vec![0, 1, 3]);
+    assert_eq!(log_manager.get_exist_log_number().unwrap(), vec![2]);
+}
+
+#[test]
+fn test_collect_archived_logs() {
+    let mut tmp_dir = temp_dir();
+    tmp_dir.push(format!("komachi_test_collector_{}", random::<u16>()));
+    create_dir(tmp_dir.clone()).unwrap();
+
+    let mut log_manager = LogManager::new(tmp_dir.clone(), 0, 4 * 1024, "test").unwrap();
+    log_manager.set_archive(Some(Duration::from_secs(0)), None);
+    let log_manager = Arc::new(log_manager);
+
+    for log_number in 1..4 {
+        log_manager.freeze_current_file(log_number).unwrap();
+    }
+
+    let collector = ObsoleteFileCollector::new(log_manager.clone());
+    let pin = collector.pin_log(1);
+
+    // the archived logs expire at once, the pinned one is neither archived nor purged
+    collector.collect([3].iter().cloned().collect::<HashSet<_>>());
+    collector.wait();
+    assert_eq!(log_manager.all_log_numbers().unwrap(), vec![1, 3]);
+
+    drop(pin);
+    collector.wait();
+    assert_eq!(log_manager.all_log_numbers().unwrap(), vec![3]);
+}
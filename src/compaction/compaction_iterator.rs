@@ -0,0 +1,166 @@
+use crate::format::{SequenceNumber, ValueTag, ValueType};
+use crate::memtable::{InternalKey, RangeTombstone};
+use crate::{CompactionDecision, CompactionFilter, Comparator};
+use bytes::Bytes;
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::iter::Peekable;
+use std::marker::PhantomData;
+
+/// Rewrites a stream of internal keys sorted by `InternalKeyComparator`, which is the order
+/// produced by memtable iterators.
+///
+/// Every version that is overwritten inside the same snapshot stripe is dropped. A stripe is
+/// the range of sequence numbers between two adjacent snapshots, so each snapshot keeps seeing
+/// exactly the version it saw before the compaction. A version deleted by a range tombstone of
+/// the same stripe is dropped as well.
+pub struct CompactionIterator<'a, C: Comparator, I: Iterator<Item = (&'a [u8], &'a [u8])>> {
+    input: Peekable<I>,
+    level: usize,
+    filter: Option<&'a dyn CompactionFilter>,
+    snapshots: Vec<SequenceNumber>,
+    bottommost: bool,
+    range_tombstones: Vec<RangeTombstone>,
+    output: VecDeque<(InternalKey, Bytes)>,
+    _key_comparator: PhantomData<C>,
+}
+
+#[allow(dead_code)]
+impl<'a, C: Comparator, I: Iterator<Item = (&'a [u8], &'a [u8])>> CompactionIterator<'a, C, I> {
+    /// `snapshots` must be sorted in ascending order. `bottommost` means that no older version of
+    /// any key exists outside of `input`, so tombstones without older versions can be dropped.
+    pub fn new(
+        input: I,
+        level: usize,
+        filter: Option<&'a dyn CompactionFilter>,
+        snapshots: Vec<SequenceNumber>,
+        bottommost: bool,
+    ) -> Self {
+        CompactionIterator {
+            input: input.peekable(),
+            level,
+            filter,
+            snapshots,
+            bottommost,
+            range_tombstones: vec![],
+            output: VecDeque::new(),
+            _key_comparator: PhantomData,
+        }
+    }
+
+    /// The range tombstones that delete versions of `input`, they are not part of the output.
+    pub fn set_range_tombstones(&mut self, range_tombstones: Vec<RangeTombstone>) {
+        self.range_tombstones = range_tombstones;
+    }
+
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    // all versions of the next user key, from the oldest to the newest
+    fn next_versions(&mut self) -> Vec<(ValueTag, &'a [u8], &'a [u8])> {
+        let mut versions: Vec<(ValueTag, &'a [u8], &'a [u8])> = vec![];
+
+        while let Some((internal_key, value)) = self.input.peek().cloned() {
+            let (tag, key) = InternalKey::split(internal_key);
+
+            if let Some((_, first_key, _)) = versions.first() {
+                if C::compare(first_key, key) != Ordering::Equal {
+                    break;
+                }
+            }
+
+            versions.push((tag, key, value));
+            self.input.next();
+        }
+
+        versions
+    }
+
+    // index of the earliest snapshot that can observe `sequence`, `snapshots.len()` for the tip
+    fn stripe(&self, sequence: SequenceNumber) -> usize {
+        match self.snapshots.binary_search(&sequence) {
+            Ok(index) => index,
+            Err(index) => index,
+        }
+    }
+
+    // whether a range tombstone no snapshot can tell from the version deletes it
+    fn deleted_by_range(&self, key: &[u8], sequence: SequenceNumber) -> bool {
+        let stripe = self.stripe(sequence);
+
+        self.range_tombstones.iter().any(|tombstone| {
+            tombstone.sequence > sequence
+                && self.stripe(tombstone.sequence) == stripe
+                && tombstone.covers::<C>(key)
+        })
+    }
+
+    fn apply_filter(&self, key: &[u8], tag: ValueTag, value: &[u8]) -> (ValueTag, Bytes) {
+        // a version observed by a live snapshot is never rewritten, the snapshot would change
+        let filter = match self.filter {
+            Some(filter)
+                if tag.is_value() && self.stripe(tag.sequence_number) == self.snapshots.len() =>
+            {
+                filter
+            }
+            _ => return (tag, Bytes::copy_from_slice(value)),
+        };
+
+        match filter.filter(self.level, key, value, tag) {
+            CompactionDecision::Keep => (tag, Bytes::copy_from_slice(value)),
+            CompactionDecision::Remove => (
+                ValueTag {
+                    sequence_number: tag.sequence_number,
+                    ty: ValueType::TombStone,
+                },
+                Bytes::new(),
+            ),
+            CompactionDecision::ChangeValue(new_value) => (tag, new_value),
+        }
+    }
+}
+
+impl<'a, C: Comparator, I: Iterator<Item = (&'a [u8], &'a [u8])>> Iterator
+    for CompactionIterator<'a, C, I>
+{
+    type Item = (InternalKey, Bytes);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.output.is_empty() {
+            let mut versions = self.next_versions();
+
+            let (newest_tag, key, newest_value) = versions.pop()?;
+            let user_key = Bytes::copy_from_slice(key);
+
+            let mut kept = vec![];
+            let mut last_stripe = self.stripe(newest_tag.sequence_number);
+
+            for (tag, _, value) in versions.into_iter().rev() {
+                let stripe = self.stripe(tag.sequence_number);
+
+                if stripe != last_stripe {
+                    kept.push((tag, Bytes::copy_from_slice(value)));
+                    last_stripe = stripe;
+                }
+            }
+
+            kept.reverse();
+            kept.push(self.apply_filter(key, newest_tag, newest_value));
+            kept.retain(|(tag, _)| !self.deleted_by_range(key, tag.sequence_number));
+
+            if self.bottommost {
+                while kept.first().map(|(tag, _)| tag.is_tombstone()) == Some(true) {
+                    kept.remove(0);
+                }
+            }
+
+            self.output.extend(
+                kept.into_iter()
+                    .map(|(tag, value)| (InternalKey::new(user_key.clone(), tag), value)),
+            );
+        }
+
+        self.output.pop_front()
+    }
+}
@@ -0,0 +1,161 @@
+use crate::compaction::CompactionIterator;
+use crate::format::{SequenceNumber, ValueTag, ValueType};
+use crate::memtable::{InternalKey, MemTable};
+use crate::{CompactionFilter, Comparator, IOPriority, RateLimiter};
+use bytes::Bytes;
+use std::cmp::{max, min, Ordering};
+use std::thread::scope;
+
+/// Partitions smaller than this are not worth a thread of their own.
+pub const MIN_SUBCOMPACTION_ENTRIES: usize = 16 * 1024;
+
+/// Compacts a memtable, splitting large inputs into key ranges that are compacted concurrently.
+pub struct CompactionJob<'a, C: Comparator> {
+    memtable: &'a MemTable<C>,
+    level: usize,
+    filter: Option<&'a dyn CompactionFilter>,
+    snapshots: Vec<SequenceNumber>,
+    bottommost: bool,
+    max_subcompactions: usize,
+    rate_limiter: Option<&'a RateLimiter>,
+    io_priority: IOPriority,
+}
+
+#[allow(dead_code)]
+impl<'a, C: Comparator> CompactionJob<'a, C> {
+    pub fn new(
+        memtable: &'a MemTable<C>,
+        level: usize,
+        filter: Option<&'a dyn CompactionFilter>,
+        snapshots: Vec<SequenceNumber>,
+        bottommost: bool,
+        max_subcompactions: usize,
+        rate_limiter: Option<&'a RateLimiter>,
+    ) -> Self {
+        CompactionJob {
+            memtable,
+            level,
+            filter,
+            snapshots,
+            bottommost,
+            max_subcompactions,
+            rate_limiter,
+            io_priority: IOPriority::Compaction,
+        }
+    }
+
+    /// The priority the output is charged to the rate limiter at, `IOPriority::Compaction` by
+    /// default.
+    pub fn set_io_priority(&mut self, io_priority: IOPriority) {
+        self.io_priority = io_priority;
+    }
+
+    /// User keys splitting the input into partitions of similar size. All versions of a key
+    /// belong to the same partition.
+    pub fn boundaries(&self) -> Vec<Bytes> {
+        let partitions = max(
+            min(
+                self.max_subcompactions,
+                self.memtable.len() / MIN_SUBCOMPACTION_ENTRIES,
+            ),
+            1,
+        );
+        let step = self.memtable.len() / partitions;
+        let mut boundaries: Vec<Bytes> = vec![];
+
+        if partitions == 1 {
+            return boundaries;
+        }
+
+        for (index, (internal_key, _)) in self.memtable.iter().enumerate() {
+            if boundaries.len() == partitions - 1 {
+                break;
+            }
+
+            if index == 0 || index % step != 0 {
+                continue;
+            }
+
+            let key = InternalKey::split_key(internal_key);
+
+            if boundaries
+                .last()
+                .map(|last| C::compare(last.as_ref(), key) != Ordering::Equal)
+                .unwrap_or(true)
+            {
+                boundaries.push(Bytes::copy_from_slice(key));
+            }
+        }
+
+        boundaries
+    }
+
+    /// Returns the compacted entries in internal key order.
+    pub fn run(&self) -> Vec<(InternalKey, Bytes)> {
+        let boundaries = self.boundaries();
+
+        if boundaries.is_empty() {
+            return self.compact_range(None, None);
+        }
+
+        let mut ranges = vec![(None, boundaries.first())];
+        ranges.extend(
+            boundaries
+                .iter()
+                .enumerate()
+                .map(|(index, begin)| (Some(begin), boundaries.get(index + 1))),
+        );
+
+        scope(|s| {
+            let handles: Vec<_> = ranges
+                .into_iter()
+                .map(|(begin, end)| s.spawn(move || self.compact_range(begin, end)))
+                .collect();
+
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        })
+    }
+
+    fn compact_range(
+        &self,
+        begin: Option<&Bytes>,
+        end: Option<&Bytes>,
+    ) -> Vec<(InternalKey, Bytes)> {
+        let input = match begin {
+            // sequence 0 sorts before every other version of the key
+            Some(key) => self.memtable.iter_from(&InternalKey::new(
+                key.clone(),
+                ValueTag {
+                    sequence_number: 0,
+                    ty: ValueType::Value,
+                },
+            )),
+            None => self.memtable.iter(),
+        }
+        .take_while(|(internal_key, _)| {
+            end.map(|end| {
+                C::compare(InternalKey::split_key(internal_key), end.as_ref()) == Ordering::Less
+            })
+            .unwrap_or(true)
+        });
+
+        let mut iter = CompactionIterator::<C, _>::new(
+            input,
+            self.level,
+            self.filter,
+            self.snapshots.clone(),
+            self.bottommost,
+        );
+        iter.set_range_tombstones(self.memtable.range_tombstones());
+
+        iter.inspect(|(key, value)| {
+            if let Some(rate_limiter) = self.rate_limiter {
+                rate_limiter.request(key.key().len() + value.len(), self.io_priority);
+            }
+        })
+        .collect()
+    }
+}
@@ -0,0 +1,7 @@
+mod compaction_iterator;
+mod compaction_job;
+#[cfg(test)]
+mod tests;
+
+pub use compaction_iterator::CompactionIterator;
+pub use compaction_job::CompactionJob;
@@ -0,0 +1,254 @@
+use crate::compaction::compaction_job::MIN_SUBCOMPACTION_ENTRIES;
+use crate::compaction::{CompactionIterator, CompactionJob};
+use crate::format::{ValueTag, ValueType};
+use crate::helper::test::*;
+use crate::memtable::internal_key::InternalKeyComparator;
+use crate::memtable::{InternalKey, MemTable, MemTableMut, RangeTombstone};
+use crate::skip_list::RandomLevelGenerator;
+use crate::{CompactionDecision, CompactionFilter, Comparator, NumberComparator};
+use bytes::Bytes;
+
+type TestComparator = NumberComparator<u32>;
+
+struct OddKeyFilter;
+
+impl CompactionFilter for OddKeyFilter {
+    fn name(&self) -> &str {
+        "OddKeyFilter"
+    }
+
+    fn filter(
+        &self,
+        level: usize,
+        key: &[u8],
+        value: &[u8],
+        value_tag: ValueTag,
+    ) -> CompactionDecision {
+        assert_eq!(level, 1);
+        assert!(value_tag.is_value());
+        assert_eq!(get_u32(key), get_u32(value));
+
+        match get_u32(key) {
+            k if k % 2 == 1 => CompactionDecision::Remove,
+            k if k % 4 == 0 => CompactionDecision::ChangeValue(get_bytes(k + 1)),
+            _ => CompactionDecision::Keep,
+        }
+    }
+}
+
+fn create_memtable() -> MemTableMut<TestComparator> {
+    MemTableMut::new(0, Box::new(RandomLevelGenerator::new(10, 0.1)), 4 * 1024)
+}
+
+fn add(memtable: &MemTableMut<TestComparator>, key: u32, sequence: u64, ty: ValueType) {
+    let value = match ty {
+        ValueType::Value => get_bytes(key),
+        _ => Bytes::new(),
+    };
+
+    memtable.add(
+        InternalKey::new(get_bytes(key), ValueTag::new(sequence, ty).unwrap()),
+        value,
+    );
+}
+
+#[test]
+fn test_compaction_filter() {
+    let memtable = create_memtable();
+
+    for (key, _) in generate_data(0, 1000) {
+        add(&memtable, key, 1, ValueType::Value);
+    }
+
+    for key in 0..10 {
+        add(&memtable, key, 2, ValueType::TombStone);
+    }
+
+    let filter = OddKeyFilter;
+    let result = CompactionIterator::<TestComparator, _>::new(
+        memtable.iter(),
+        1,
+        Some(&filter),
+        vec![],
+        false,
+    )
+    .map(|(key, value)| (get_u32(key.key()), key.value_tag(), value))
+    .collect::<Vec<_>>();
+
+    assert_eq!(result.len(), 1000);
+
+    for (key, tag, value) in result {
+        if key < 10 {
+            assert_eq!(value.is_empty(), tag.is_tombstone());
+            assert_eq!(tag.is_tombstone(), tag.sequence_number == 2);
+        } else if key % 2 == 1 {
+            assert!(tag.is_tombstone());
+            assert_eq!(tag.sequence_number, 1);
+        } else if key % 4 == 0 {
+            assert!(tag.is_value());
+            assert_eq!(get_u32(value.as_ref()), key + 1);
+        } else {
+            assert!(tag.is_value());
+            assert_eq!(get_u32(value.as_ref()), key);
+        }
+    }
+}
+
+#[test]
+fn test_compaction_order() {
+    let memtable = create_memtable();
+
+    for (key, _) in generate_data(0, 100) {
+        add(&memtable, key, key as u64 % 3 + 1, ValueType::Value);
+        add(&memtable, key, key as u64 % 3 + 4, ValueType::Value);
+    }
+
+    let result = CompactionIterator::<TestComparator, _>::new(
+        memtable.iter(),
+        0,
+        None,
+        vec![0, 1, 2, 3, 4, 5, 6],
+        false,
+    )
+    .map(|(key, _)| key.as_bytes())
+    .collect::<Vec<_>>();
+
+    assert_eq!(result.len(), 200);
+
+    for pair in result.windows(2) {
+        assert_eq!(
+            InternalKeyComparator::<TestComparator>::compare(pair[0].as_ref(), pair[1].as_ref()),
+            std::cmp::Ordering::Less
+        );
+    }
+}
+
+#[test]
+fn test_garbage_collection() {
+    let memtable = create_memtable();
+
+    for key in 0..100 {
+        for sequence in 1..=10 {
+            let ty = if sequence == 10 && key % 2 == 0 {
+                ValueType::TombStone
+            } else {
+                ValueType::Value
+            };
+
+            add(&memtable, key, sequence, ty);
+        }
+    }
+
+    let snapshots = vec![3, 4, 8];
+
+    let result = CompactionIterator::<TestComparator, _>::new(
+        memtable.iter(),
+        0,
+        None,
+        snapshots.clone(),
+        false,
+    )
+    .map(|(key, _)| (get_u32(key.key()), key.value_tag()))
+    .collect::<Vec<_>>();
+
+    for key in 0..100 {
+        let sequences = result
+            .iter()
+            .filter(|(k, _)| *k == key)
+            .map(|(_, tag)| tag.sequence_number)
+            .collect::<Vec<_>>();
+
+        assert_eq!(sequences, vec![3, 4, 8, 10]);
+    }
+
+    // without any older data, a tombstone at the bottom hides nothing
+    let bottommost =
+        CompactionIterator::<TestComparator, _>::new(memtable.iter(), 0, None, vec![], true)
+            .map(|(key, _)| (get_u32(key.key()), key.value_tag()))
+            .collect::<Vec<_>>();
+
+    assert_eq!(bottommost.len(), 50);
+
+    for (key, tag) in bottommost {
+        assert_eq!(key % 2, 1);
+        assert!(tag.is_value());
+        assert_eq!(tag.sequence_number, 10);
+    }
+}
+
+#[test]
+fn test_subcompaction() {
+    let memtable = create_memtable();
+    let key_count = (MIN_SUBCOMPACTION_ENTRIES * 2) as u32;
+
+    for (key, _) in generate_data(0, key_count) {
+        add(&memtable, key, 1, ValueType::Value);
+        add(&memtable, key, 3, ValueType::Value);
+    }
+
+    let memtable: MemTable<TestComparator> = memtable.freeze();
+    let filter = OddKeyFilter;
+    let job = CompactionJob::new(&memtable, 1, Some(&filter), vec![2], false, 8, None);
+
+    assert_eq!(job.boundaries().len(), 3);
+
+    let expected = CompactionIterator::<TestComparator, _>::new(
+        memtable.iter(),
+        1,
+        Some(&filter),
+        vec![2],
+        false,
+    )
+    .map(|(key, value)| (key.as_bytes(), value))
+    .collect::<Vec<_>>();
+
+    let result = job
+        .run()
+        .into_iter()
+        .map(|(key, value)| (key.as_bytes(), value))
+        .collect::<Vec<_>>();
+
+    assert_eq!(result.len(), key_count as usize * 2);
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_range_tombstones() {
+    let memtable = create_memtable();
+
+    for key in 0..100 {
+        add(&memtable, key, 1, ValueType::Value);
+        add(&memtable, key, 4, ValueType::Value);
+    }
+
+    let tombstone = |begin: u32, end: u32, sequence: u64| RangeTombstone {
+        begin: get_bytes(begin),
+        end: get_bytes(end),
+        sequence,
+    };
+
+    // the snapshot at 2 sees the first versions, a tombstone after it cannot delete them
+    let mut iter =
+        CompactionIterator::<TestComparator, _>::new(memtable.iter(), 0, None, vec![2], false);
+    iter.set_range_tombstones(vec![
+        tombstone(10, 20, 5),
+        tombstone(30, 40, 2),
+        tombstone(50, 60, 2),
+        tombstone(50, 60, 6),
+    ]);
+
+    let result = iter
+        .map(|(key, _)| (get_u32(key.key()), key.value_tag().sequence_number))
+        .collect::<Vec<_>>();
+
+    let expected = (0..100u32)
+        .flat_map(|key| match key {
+            10..=19 => vec![(key, 1)],
+            30..=39 => vec![(key, 4)],
+            50..=59 => vec![],
+            _ => vec![(key, 1), (key, 4)],
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(result, expected);
+}
@@ -1,26 +1,451 @@
-use crate::error::Result;
-use crate::logger::LogManager;
-use crate::memtable::{MemTable, MemTableMut};
+use crate::collector::{LogPin, ObsoleteFileCollector};
+use crate::compaction::CompactionJob;
+use crate::error::{Error, Result};
+use crate::format::{SequenceNumber, ValueTag, ValueType};
+use crate::logger::{LogManager, LogNumber, RecoveryReport};
+use crate::memtable::internal_key::InternalKeyComparator;
+use crate::memtable::{new_rep, InternalKey, MemTable, MemTableMut, SkipListSettings};
+use crate::options::{Options, WriteOptions};
 use crate::session::{Session, SessionFactory};
-use crate::skip_list::RandomLevelGenerator;
-use crate::Comparator;
+use crate::skip_list::RangeEstimate;
+use crate::write_batch::WriteBatch;
+use crate::write_buffer_manager::WriteBufferCharge;
+use crate::{Comparator, IOPriority};
+use bytes::Bytes;
+use std::cmp::max;
+use std::collections::{HashMap, HashSet};
+use std::fs::create_dir_all;
 use std::mem::replace;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::thread::{spawn, JoinHandle};
+use std::time::Duration;
+
+enum CompactionTask {
+    // the log number of the memtable and its memory usage
+    Compact(LogNumber, u64),
+    Wait(Sender<()>),
+    Stop,
+}
 
 #[allow(dead_code)]
 pub struct DBCore<C: Comparator> {
+    options: Options,
     session_factory: SessionFactory,
     memtable_log_number: AtomicU64,
     mutable_memtable: RwLock<MemTableMut<C>>,
-    immutable_memtables: RwLock<Vec<MemTable<C>>>,
-    log_manager: LogManager,
+    immutable_memtables: RwLock<Vec<Arc<MemTable<C>>>>,
+    immutable_memory_usage: AtomicUsize,
+    write_buffer_charge: Option<WriteBufferCharge>,
+    compaction_lock: Mutex<()>,
+    // set once the background compaction is started
+    compaction_sender: Mutex<Option<Sender<CompactionTask>>>,
+    compaction_handle: Mutex<Option<JoinHandle<()>>>,
+    // the memory of the frozen memtables waiting for their compaction
+    pending_compaction_bytes: AtomicU64,
+    log_manager: Arc<LogManager>,
+    file_collector: ObsoleteFileCollector,
+    recovery_report: RecoveryReport,
 }
 
+unsafe impl<C: Comparator> Send for DBCore<C> {}
 unsafe impl<C: Comparator> Sync for DBCore<C> {}
 
 #[allow(dead_code)]
 impl<C: Comparator> DBCore<C> {
+    pub fn new(options: Options) -> Result<Self> {
+        options.validate()?;
+
+        create_dir_all(&options.dir).map_err(|_| {
+            Error::UnableToCreateFile(options.dir.as_os_str().to_str().unwrap().to_string())
+        })?;
+
+        // archived and lost logs keep their numbers, a new log must not reuse one
+        let first_log_number = LogManager::exist_log_numbers(options.dir.as_path())?
+            .into_iter()
+            .chain(LogManager::archived_log_numbers(options.dir.as_path())?)
+            .chain(LogManager::lost_log_numbers(options.dir.as_path())?)
+            .max()
+            .map(|n| n + 1)
+            .unwrap_or(0);
+
+        let mut log_manager = LogManager::new(
+            options.dir.clone(),
+            first_log_number,
+            options.log_block_size,
+            C::name(),
+        )?;
+        log_manager.set_rate_limiter(options.rate_limiter.clone());
+        log_manager.set_sync_mode(options.wal_sync_mode);
+        log_manager.set_recovery_mode(options.wal_recovery_mode);
+        log_manager.set_recycle_log_file_num(options.recycle_log_file_num);
+        log_manager.set_preallocate_size(options.log_preallocate_size);
+        log_manager.set_compression(options.wal_compression);
+        log_manager.set_archive(options.wal_ttl, options.wal_size_limit);
+        log_manager.set_manual_flush(options.manual_wal_flush);
+        log_manager.purge_archive(&HashMap::new())?;
+        let log_manager = Arc::new(log_manager);
+        LogManager::start_periodic_sync(&log_manager);
+
+        let mut live_logs = HashSet::new();
+        live_logs.insert(first_log_number);
+
+        let mut exist_log_numbers = log_manager.get_exist_log_number()?;
+        exist_log_numbers.sort_unstable();
+
+        let mut immutable_memtables = vec![];
+        let mut next_sequence = 0;
+        let mut recovery_report = RecoveryReport {
+            unlogged_writes_lost: log_manager.unlogged_marker_found(),
+            ..RecoveryReport::default()
+        };
+
+        for log_number in exist_log_numbers {
+            // the logs after the consistent point are kept out of the way of the next recovery
+            if recovery_report.stopped_early {
+                log_manager.set_aside_log(log_number)?;
+                recovery_report.lost_logs.push(log_number);
+                continue;
+            }
+
+            let (memtable, sequence, report) = Self::recover_memtable(
+                log_manager.as_ref(),
+                &options,
+                log_number,
+                immutable_memtables.as_slice(),
+            )?;
+
+            if !memtable.is_empty() {
+                live_logs.insert(log_number);
+                immutable_memtables.push(Arc::new(memtable.freeze()));
+                next_sequence = max(next_sequence, sequence + 1);
+            } else if report.stopped_early {
+                // nothing of it was replayed, the corrupted log would be collected as an orphan
+                log_manager.set_aside_log(log_number)?;
+                recovery_report.lost_logs.push(log_number);
+            }
+
+            recovery_report.merge(&report);
+        }
+
+        ObsoleteFileCollector::collect_orphans(log_manager.as_ref(), &live_logs)?;
+
+        let mutable_memtable = Self::new_memtable(&options, first_log_number);
+        let immutable_memory_usage = Self::memory_usage_of(immutable_memtables.as_slice());
+        let write_buffer_charge = options.write_buffer_manager.clone().map(|manager| {
+            let charge = WriteBufferCharge::new(manager);
+            charge.update(mutable_memtable.memory_usage(), immutable_memory_usage);
+
+            charge
+        });
+
+        Ok(DBCore {
+            session_factory: SessionFactory::new(next_sequence),
+            memtable_log_number: AtomicU64::new(first_log_number + 1),
+            mutable_memtable: RwLock::new(mutable_memtable),
+            immutable_memtables: RwLock::new(immutable_memtables),
+            immutable_memory_usage: AtomicUsize::new(immutable_memory_usage),
+            write_buffer_charge,
+            compaction_lock: Mutex::new(()),
+            compaction_sender: Mutex::new(None),
+            compaction_handle: Mutex::new(None),
+            pending_compaction_bytes: AtomicU64::new(0),
+            file_collector: ObsoleteFileCollector::new(log_manager.clone()),
+            log_manager,
+            recovery_report,
+            options,
+        })
+    }
+
+    // replays a log into a new memtable, returns the largest sequence number found
+    fn recover_memtable(
+        log_manager: &LogManager,
+        options: &Options,
+        log_number: LogNumber,
+        // the memtables of the logs replayed before
+        older: &[Arc<MemTable<C>>],
+    ) -> Result<(MemTableMut<C>, SequenceNumber, RecoveryReport)> {
+        let memtable = Self::new_memtable(options, log_number);
+        let mut last_sequence = 0;
+        let mut iter = log_manager.log_iterator(log_number)?;
+        let legacy = iter.is_legacy();
+
+        for wrapper in iter.by_ref() {
+            let wrapper = wrapper?;
+            let record = wrapper.record();
+            let internal_key = InternalKey::from_logged(record.key(), legacy);
+
+            last_sequence = max(last_sequence, internal_key.value_tag().sequence_number);
+            // the in-place updates are replayed the same way, the merges give the same values
+            Self::insert(
+                options,
+                &memtable,
+                internal_key,
+                Bytes::copy_from_slice(record.value()),
+                |key, sequence| Self::get_from_memtables(older, key, sequence),
+            )?;
+        }
+
+        Ok((memtable, last_sequence, iter.report().clone()))
+    }
+
+    pub fn recovery_report(&self) -> &RecoveryReport {
+        &self.recovery_report
+    }
+
+    fn memory_usage_of(memtables: &[Arc<MemTable<C>>]) -> usize {
+        memtables
+            .iter()
+            .map(|memtable| memtable.memory_usage())
+            .sum()
+    }
+
+    fn charge_write_buffer(&self, mutable_memory_usage: usize) {
+        if let Some(charge) = self.write_buffer_charge.as_ref() {
+            charge.update(
+                mutable_memory_usage,
+                self.immutable_memory_usage.load(Ordering::SeqCst),
+            );
+        }
+    }
+
+    pub fn write(
+        &self,
+        options: &WriteOptions,
+        key: Bytes,
+        value: Bytes,
+        ty: ValueType,
+    ) -> Result<()> {
+        let mut batch = WriteBatch::new();
+
+        match ty {
+            ValueType::Value => batch.put(key, value),
+            ValueType::TombStone => batch.delete(key),
+            ValueType::RangeDeletion => batch.delete_range(key, value),
+        }
+
+        self.write_batch(options, batch)
+    }
+
+    /// Logs the batch, then inserts it into the memtable. The writers of a commit group insert
+    /// their batches concurrently, and a batch becomes visible once it and every batch with
+    /// smaller sequence numbers are inserted. Returns once the batch is visible.
+    pub fn write_batch(&self, options: &WriteOptions, batch: WriteBatch) -> Result<()> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        if self.session_factory.is_stopped() {
+            return Err(Error::WritesStopped);
+        }
+
+        // the memtable is flushed by the writers of its own database only
+        if let Some(charge) = self.write_buffer_charge.as_ref() {
+            charge.flush_if_requested(|| self.renew_memtable())?;
+            charge.stall_if_needed(options.no_slowdown)?;
+        }
+
+        // the memtable must not be rotated between logging and inserting
+        let memtable = self.mutable_memtable.read().unwrap();
+
+        // the sequences are published when dropped on a failure before the memtable, so later
+        // writes are not held back
+        let assign = || -> Result<_> {
+            let sequences = self.session_factory.next_sequences(batch.len() as u64);
+            let internal_keys = batch
+                .entries()
+                .iter()
+                .enumerate()
+                .map(|(index, entry)| {
+                    ValueTag::new(sequences.first() + index as u64, entry.ty)
+                        .map(|tag| InternalKey::new(entry.key.clone(), tag))
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            Ok((sequences, internal_keys))
+        };
+
+        let (sequences, internal_keys) = if options.disable_wal {
+            self.log_manager.skip_record()?;
+            assign()?
+        } else {
+            // the sequences are taken as the write takes its place in the log, so the log is in
+            // the order of the sequences
+            let mut assigned = None;
+
+            self.log_manager.insert_records_with(options.sync, || {
+                let (sequences, internal_keys) = assign()?;
+                let records = internal_keys
+                    .iter()
+                    .zip(batch.entries())
+                    .map(|(internal_key, entry)| (internal_key.as_bytes(), entry.value.clone()))
+                    .collect();
+                assigned = Some((sequences, internal_keys));
+
+                Ok(records)
+            })?;
+
+            assigned.unwrap()
+        };
+
+        // an update in place rewrites the newest version found, which must be the newest one
+        // before the write: the writes, tombstones included, reach the memtable in the order of
+        // their sequences
+        if self.options.inplace_update_support {
+            sequences.wait_turn()?;
+        }
+
+        for (internal_key, entry) in internal_keys.into_iter().zip(batch.entries()) {
+            let inserted = Self::insert(
+                &self.options,
+                &memtable,
+                internal_key,
+                entry.value.clone(),
+                |key, sequence| {
+                    let memtables = self.immutable_memtables.read().unwrap().clone();
+
+                    Self::get_from_memtables(memtables.as_slice(), key, sequence)
+                },
+            );
+
+            // the entries inserted before must never be read
+            if let Err(error) = inserted {
+                sequences.fail();
+                return Err(error);
+            }
+        }
+
+        self.charge_write_buffer(memtable.memory_usage());
+        drop(memtable);
+
+        sequences.publish()
+    }
+
+    // `previous` reads the newest version of a key not newer than a sequence number from the
+    // older memtables
+    fn insert<F>(
+        options: &Options,
+        memtable: &MemTableMut<C>,
+        internal_key: InternalKey,
+        value: Bytes,
+        previous: F,
+    ) -> Result<()>
+    where
+        F: FnOnce(&Bytes, SequenceNumber) -> Result<Option<(ValueTag, Bytes)>>,
+    {
+        if internal_key.value_tag().is_range_deletion() {
+            memtable.add_range_tombstone(internal_key, value);
+            Ok(())
+        } else if options.inplace_update_support && internal_key.value_tag().is_value() {
+            let key = internal_key.key().clone();
+            let sequence = internal_key.value_tag().sequence_number;
+
+            memtable.update(
+                internal_key,
+                value,
+                options.inplace_callback.as_deref(),
+                || {
+                    Ok(previous(&key, sequence)?
+                        .filter(|(tag, _)| tag.is_value())
+                        .map(|(_, value)| value))
+                },
+            )
+        } else if options.memtable_insert_with_hint {
+            memtable.add_with_hint(internal_key, value);
+            Ok(())
+        } else {
+            memtable.add(internal_key, value);
+            Ok(())
+        }
+    }
+
+    // the newest version of `key` not newer than `sequence`, the newest memtable is the last one.
+    // A version deleted by a range deletion of these memtables reads as a tombstone.
+    fn get_from_memtables(
+        memtables: &[Arc<MemTable<C>>],
+        key: &Bytes,
+        sequence: SequenceNumber,
+    ) -> Result<Option<(ValueTag, Bytes)>> {
+        let deleted_at = memtables
+            .iter()
+            .filter_map(|memtable| memtable.max_covering_tombstone(key.as_ref(), sequence))
+            .max();
+
+        for memtable in memtables.iter().rev() {
+            if let Some((tag, value)) = memtable.seek_by_key_and_sequence(key, sequence)? {
+                return Ok(Some(Self::apply_range_deletion(
+                    (tag, Bytes::copy_from_slice(value)),
+                    deleted_at,
+                )));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn apply_range_deletion(
+        (tag, value): (ValueTag, Bytes),
+        deleted_at: Option<SequenceNumber>,
+    ) -> (ValueTag, Bytes) {
+        match deleted_at {
+            Some(deleted_at) if tag.sequence_number < deleted_at => (
+                ValueTag {
+                    sequence_number: deleted_at,
+                    ty: ValueType::TombStone,
+                },
+                Bytes::new(),
+            ),
+            _ => (tag, value),
+        }
+    }
+
+    pub fn flush_wal(&self, sync: bool) -> Result<()> {
+        self.log_manager.flush(sync)
+    }
+
+    /// Reads the newest version of `key` whose sequence number is less than `sequence`.
+    pub fn get(&self, key: &Bytes, sequence: SequenceNumber) -> Result<Option<Bytes>> {
+        let sequence = match sequence.checked_sub(1) {
+            Some(sequence) => sequence,
+            None => return Ok(None),
+        };
+
+        let memtable = self.mutable_memtable.read().unwrap();
+        let deleted_at = memtable.max_covering_tombstone(key.as_ref(), sequence);
+        let mut result = memtable
+            .get(key, sequence)?
+            .map(|result| Self::apply_range_deletion(result, deleted_at));
+
+        if result.is_none() {
+            let memtables = self.immutable_memtables.read().unwrap().clone();
+
+            result = Self::get_from_memtables(memtables.as_slice(), key, sequence)?
+                .map(|result| Self::apply_range_deletion(result, deleted_at));
+        }
+
+        Ok(result
+            .filter(|(tag, _)| tag.is_value())
+            .map(|(_, value)| value))
+    }
+
+    /// Estimates the versions of the keys in `[begin, end)` held by all the memtables.
+    pub fn estimate_range(&self, begin: &Bytes, end: &Bytes) -> RangeEstimate {
+        let memtable = self.mutable_memtable.read().unwrap();
+        let memtables = self.immutable_memtables.read().unwrap().clone();
+
+        memtables
+            .iter()
+            .map(|memtable| memtable.estimate_range(begin, end))
+            .fold(memtable.estimate_range(begin, end), |sum, estimate| {
+                sum + estimate
+            })
+    }
+
+    pub fn last_sequence(&self) -> SequenceNumber {
+        self.session_factory.last_sequence()
+    }
+
     pub fn get_session(&self, core_arc: Arc<DBCore<C>>) -> Session<C> {
         self.session_factory.get_session(core_arc)
     }
@@ -29,22 +454,239 @@ impl<C: Comparator> DBCore<C> {
         self.session_factory.drop_sequence(sequence)
     }
 
+    fn new_memtable(options: &Options, log_number: u64) -> MemTableMut<C> {
+        let mut memtable = MemTableMut::with_rep(
+            log_number,
+            new_rep::<InternalKeyComparator<C>>(
+                options.memtable_rep,
+                SkipListSettings::from_options(options),
+            ),
+        );
+
+        if let Some(bloom_filter) = options.memtable_bloom_filter {
+            memtable.set_bloom_filter(bloom_filter);
+        }
+
+        if options.inplace_update_support {
+            memtable.set_inplace_update(options.inplace_update_num_locks);
+        }
+
+        memtable
+    }
+
     fn create_memtable(&self) -> MemTableMut<C> {
-        // todo: get level generator and block_size from config
-        MemTableMut::new(
+        Self::new_memtable(
+            &self.options,
             self.memtable_log_number.fetch_add(1, Ordering::SeqCst),
-            Box::new(RandomLevelGenerator::new(10, 0.1)),
-            1024 * 4,
         )
     }
 
-    fn renew_memtable(&self) -> Result<()> {
+    /// Starts the thread compacting the frozen memtables, it runs until
+    /// `stop_background_compaction` is called or the core is dropped. Without it the frozen
+    /// memtables are kept as they are.
+    pub fn start_background_compaction(core: &Arc<Self>) {
+        let (sender, receiver) = channel();
+        let weak: Weak<Self> = Arc::downgrade(core);
+
+        let handle = spawn(move || {
+            while let Ok(task) = receiver.recv() {
+                match task {
+                    CompactionTask::Compact(log_number, bytes) => match weak.upgrade() {
+                        Some(core) => {
+                            // a failed compaction leaves the memtable as it was
+                            core.compact_immutable_memtable(log_number).ok();
+                            core.pending_compaction_bytes
+                                .fetch_sub(bytes, Ordering::SeqCst);
+                            core.tune_rate_limiter();
+                        }
+                        None => break,
+                    },
+                    CompactionTask::Wait(sender) => {
+                        sender.send(()).ok();
+                    }
+                    CompactionTask::Stop => break,
+                }
+            }
+        });
+
+        *core.compaction_sender.lock().unwrap() = Some(sender);
+        *core.compaction_handle.lock().unwrap() = Some(handle);
+    }
+
+    /// Stops the background compaction once the scheduled compactions are finished.
+    pub fn stop_background_compaction(&self) {
+        if let Some(sender) = self.compaction_sender.lock().unwrap().take() {
+            sender.send(CompactionTask::Stop).ok();
+        }
+
+        if let Some(handle) = self.compaction_handle.lock().unwrap().take() {
+            handle.join().ok();
+        }
+    }
+
+    /// Blocks until every scheduled compaction is finished.
+    pub fn wait_for_compactions(&self) {
+        let (sender, receiver) = channel();
+
+        if self.send_compaction_task(CompactionTask::Wait(sender)) {
+            receiver.recv().ok();
+        }
+    }
+
+    // the pending compactions set the rate of an auto-tuned rate limiter
+    fn tune_rate_limiter(&self) {
+        if let Some(rate_limiter) = self.options.rate_limiter.as_ref() {
+            rate_limiter.tune(self.pending_compaction_bytes.load(Ordering::SeqCst));
+        }
+    }
+
+    fn schedule_compaction(&self, log_number: LogNumber, bytes: u64) {
+        self.pending_compaction_bytes
+            .fetch_add(bytes, Ordering::SeqCst);
+        self.tune_rate_limiter();
+
+        if !self.send_compaction_task(CompactionTask::Compact(log_number, bytes)) {
+            self.pending_compaction_bytes
+                .fetch_sub(bytes, Ordering::SeqCst);
+            self.tune_rate_limiter();
+        }
+    }
+
+    fn send_compaction_task(&self, task: CompactionTask) -> bool {
+        match self.compaction_sender.lock().unwrap().as_ref() {
+            Some(sender) => sender.send(task).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Freezes the mutable memtable and schedules its compaction, which runs in the background
+    /// once the writes go to the new one.
+    pub fn renew_memtable(&self) -> Result<()> {
         let mut guard = self.mutable_memtable.write().unwrap();
         let old = replace(&mut *guard, self.create_memtable());
         let immutable = old.freeze();
+        let log_number = immutable.log_number();
+        let memory_usage = immutable.memory_usage() as u64;
         self.log_manager.freeze_current_file(guard.log_number())?;
 
-        self.immutable_memtables.write().unwrap().push(immutable);
+        let mut memtables = self.immutable_memtables.write().unwrap();
+        memtables.push(Arc::new(immutable));
+        self.immutable_memory_usage.store(
+            Self::memory_usage_of(memtables.as_slice()),
+            Ordering::SeqCst,
+        );
+        drop(memtables);
+
+        self.charge_write_buffer(guard.memory_usage());
+        drop(guard);
+
+        self.schedule_compaction(log_number, memory_usage);
+        self.file_collector.collect(self.live_log_numbers());
+
+        Ok(())
+    }
+
+    pub fn log_manager(&self) -> &LogManager {
+        self.log_manager.as_ref()
+    }
+
+    /// Pins the logs `list` returns, none of them is collected between the listing and the
+    /// pinning.
+    pub fn pin_logs<F>(&self, list: F) -> Result<Vec<LogPin>>
+    where
+        F: FnOnce() -> Result<Vec<LogNumber>>,
+    {
+        self.file_collector.pin_logs(list)
+    }
+
+    /// Blocks until a write is published past `seen` or `timeout` elapses.
+    pub fn wait_for_publish(&self, seen: SequenceNumber, timeout: Duration) {
+        self.session_factory.wait_for_publish(seen, timeout)
+    }
+
+    fn live_log_numbers(&self) -> HashSet<LogNumber> {
+        let mut live_logs: HashSet<LogNumber> = self
+            .immutable_memtables
+            .read()
+            .unwrap()
+            .iter()
+            .map(|memtable| memtable.log_number())
+            .collect();
+
+        live_logs.insert(self.mutable_memtable.read().unwrap().log_number());
+
+        live_logs
+    }
+
+    /// Rewrites an immutable memtable without the versions no session can observe, then swaps
+    /// it in place of the original in a single step.
+    fn compact_immutable_memtable(&self, log_number: LogNumber) -> Result<()> {
+        let _compaction_guard = self.compaction_lock.lock().unwrap();
+
+        let (memtable, bottommost) = {
+            let memtables = self.immutable_memtables.read().unwrap();
+
+            match memtables
+                .iter()
+                .position(|memtable| memtable.log_number() == log_number)
+            {
+                Some(position) => (memtables[position].clone(), position == 0),
+                None => return Ok(()),
+            }
+        };
+
+        let mut job = CompactionJob::new(
+            memtable.as_ref(),
+            0,
+            self.options.compaction_filter.as_deref(),
+            // a session sees the versions below its sequence number
+            self.session_factory
+                .sequences_in_use()
+                .into_iter()
+                .filter_map(|sequence| sequence.checked_sub(1))
+                .collect(),
+            bottommost,
+            self.options.max_subcompactions,
+            self.options.rate_limiter.as_deref(),
+        );
+        // the frozen memtables are compacted in place of a flush
+        job.set_io_priority(IOPriority::Flush);
+
+        let compacted = Self::new_memtable(&self.options, log_number);
+
+        for (key, value) in job.run() {
+            compacted.add(key, value);
+        }
+
+        // the tombstones still delete the versions of the older memtables
+        for tombstone in memtable.range_tombstones() {
+            compacted.add_range_tombstone(
+                InternalKey::new(
+                    tombstone.begin,
+                    ValueTag::new(tombstone.sequence, ValueType::RangeDeletion)?,
+                ),
+                tombstone.end,
+            );
+        }
+
+        let mut memtables = self.immutable_memtables.write().unwrap();
+
+        if let Some(position) = memtables
+            .iter()
+            .position(|memtable| memtable.log_number() == log_number)
+        {
+            memtables[position] = Arc::new(compacted.freeze());
+        }
+
+        self.immutable_memory_usage.store(
+            Self::memory_usage_of(memtables.as_slice()),
+            Ordering::SeqCst,
+        );
+        drop(memtables);
+
+        // the mutable memtable is locked after the immutable ones are released, like `get` does
+        let mutable_memory_usage = self.mutable_memtable.read().unwrap().memory_usage();
+        self.charge_write_buffer(mutable_memory_usage);
 
         Ok(())
     }
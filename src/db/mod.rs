@@ -0,0 +1,91 @@
+#[cfg(test)]
+mod tests;
+
+use crate::core::DBCore;
+use crate::error::Result;
+use crate::format::{SequenceNumber, ValueType};
+use crate::logger::RecoveryReport;
+use crate::options::{Options, WriteOptions};
+use crate::session::Session;
+use crate::skip_list::RangeEstimate;
+use crate::update_iterator::UpdateIterator;
+use crate::write_batch::WriteBatch;
+use crate::Comparator;
+use bytes::Bytes;
+use std::sync::Arc;
+
+pub struct KomachiDB<C: Comparator> {
+    core: Arc<DBCore<C>>,
+}
+
+impl<C: Comparator> KomachiDB<C> {
+    pub fn open(options: Options) -> Result<Self> {
+        let core = Arc::new(DBCore::new(options)?);
+        DBCore::start_background_compaction(&core);
+
+        Ok(KomachiDB { core })
+    }
+
+    pub fn new_session(&self) -> Session<C> {
+        self.core.get_session(self.core.clone())
+    }
+
+    pub fn put(&self, options: &WriteOptions, key: Bytes, value: Bytes) -> Result<()> {
+        self.core.write(options, key, value, ValueType::Value)
+    }
+
+    pub fn delete(&self, options: &WriteOptions, key: Bytes) -> Result<()> {
+        self.core
+            .write(options, key, Bytes::new(), ValueType::TombStone)
+    }
+
+    /// Deletes the keys in `[begin, end)`.
+    pub fn delete_range(&self, options: &WriteOptions, begin: Bytes, end: Bytes) -> Result<()> {
+        self.core
+            .write(options, begin, end, ValueType::RangeDeletion)
+    }
+
+    /// Applies the writes of the batch with consecutive sequence numbers, they become visible
+    /// together.
+    ///
+    /// A batch that fails or panics after part of it reached the memtable is never made visible.
+    /// Nothing written after it can be made visible either, so the write fails and every later
+    /// write of the database, the ones waiting for it included, fails with
+    /// `Error::WritesStopped`. The reads are not affected.
+    pub fn write(&self, options: &WriteOptions, batch: WriteBatch) -> Result<()> {
+        self.core.write_batch(options, batch)
+    }
+
+    pub fn get(&self, key: &Bytes) -> Result<Option<Bytes>> {
+        self.core.get(key, self.core.last_sequence())
+    }
+
+    /// Estimates the number and the size of the entries with keys in `[begin, end)`, each
+    /// version of a key counting as an entry. Only part of the entries is visited.
+    pub fn estimate_range(&self, begin: &Bytes, end: &Bytes) -> RangeEstimate {
+        self.core.estimate_range(begin, end)
+    }
+
+    /// Writes the log writes buffered by `Options::manual_wal_flush`, and syncs the log if `sync`
+    /// is set.
+    pub fn flush_wal(&self, sync: bool) -> Result<()> {
+        self.core.flush_wal(sync)
+    }
+
+    /// Reads every write with a sequence number not less than `sequence` from the logs, the
+    /// iterator keeps following the writes committed after it is created.
+    pub fn get_updates_since(&self, sequence: SequenceNumber) -> Result<UpdateIterator<C>> {
+        UpdateIterator::new(self.core.clone(), sequence)
+    }
+
+    /// What the replay of the logs had to drop when the database was opened.
+    pub fn recovery_report(&self) -> &RecoveryReport {
+        self.core.recovery_report()
+    }
+}
+
+impl<C: Comparator> Drop for KomachiDB<C> {
+    fn drop(&mut self) {
+        self.core.stop_background_compaction();
+    }
+}
@@ -0,0 +1,787 @@
+use crate::db::KomachiDB;
+use crate::format::{ValueTag, ValueType};
+use crate::helper::test::get_bytes;
+use crate::logger::LogManager;
+use crate::memtable::InternalKey;
+use crate::options::{Options, WriteOptions};
+use crate::write_batch::WriteBatch;
+use crate::{
+    CompactionDecision, CompactionFilter, Error, InplaceCallback, InplaceDecision, MemTableRepType,
+    NumberComparator, RateLimiter, WalRecoveryMode, WalSyncMode, WriteBufferManager,
+};
+use bytes::Bytes;
+use rand::random;
+use std::convert::TryInto;
+use std::env::temp_dir;
+use std::fs::OpenOptions;
+use std::io::{Seek, SeekFrom, Write};
+use std::mem::forget;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+type TestDB = KomachiDB<NumberComparator<u32>>;
+
+fn open_db<F: FnOnce(&mut Options)>(dir: &Path, configure: F) -> TestDB {
+    let mut options = Options::new(dir.to_path_buf());
+    configure(&mut options);
+
+    TestDB::open(options).unwrap()
+}
+
+// opens a new database in a temporary directory, `configure` adjusts its options
+fn open_temp_db<F: FnOnce(&mut Options)>(configure: F) -> (TestDB, PathBuf) {
+    let mut dir = temp_dir();
+    dir.push(format!("komachi_test_db_{}", random::<u16>()));
+
+    (open_db(dir.as_path(), configure), dir)
+}
+
+#[test]
+fn test_read_write() {
+    let (db, dir) = open_temp_db(|options| {
+        options.wal_sync_mode = WalSyncMode::Periodic(Duration::from_millis(10));
+    });
+    let write_options = WriteOptions::default();
+
+    for key in 0..100 {
+        db.put(&write_options, get_bytes(key), get_bytes(key * 2))
+            .unwrap();
+    }
+
+    let session = db.new_session();
+
+    for key in 0..50 {
+        db.delete(
+            &WriteOptions {
+                sync: true,
+                ..WriteOptions::default()
+            },
+            get_bytes(key),
+        )
+        .unwrap();
+    }
+
+    for key in 0..100 {
+        let expected = if key < 50 {
+            None
+        } else {
+            Some(get_bytes(key * 2))
+        };
+
+        assert_eq!(db.get(&get_bytes(key)).unwrap(), expected);
+        assert_eq!(
+            session.get(&get_bytes(key)).unwrap(),
+            Some(get_bytes(key * 2))
+        );
+    }
+
+    drop(session);
+    drop(db);
+
+    let db = open_db(dir.as_path(), |_| ());
+
+    for key in 0..100 {
+        let expected = if key < 50 {
+            None
+        } else {
+            Some(get_bytes(key * 2))
+        };
+
+        assert_eq!(db.get(&get_bytes(key)).unwrap(), expected);
+    }
+
+    db.put(&write_options, get_bytes(0), get_bytes(1)).unwrap();
+    assert_eq!(db.get(&get_bytes(0)).unwrap(), Some(get_bytes(1)));
+}
+
+#[test]
+fn test_unlogged_writes() {
+    let (db, dir) = open_temp_db(|options| options.manual_wal_flush = true);
+    let write_options = WriteOptions::default();
+
+    for key in 0..20 {
+        db.put(&write_options, get_bytes(key), get_bytes(key))
+            .unwrap();
+
+        if key == 9 {
+            db.flush_wal(true).unwrap();
+        }
+    }
+
+    // a crash, the writes after the flush are lost
+    forget(db);
+
+    let db = open_db(dir.as_path(), |_| ());
+    assert!(db.recovery_report().unlogged_writes_lost);
+    for key in 0..20 {
+        let expected = if key < 10 { Some(get_bytes(key)) } else { None };
+        assert_eq!(db.get(&get_bytes(key)).unwrap(), expected);
+    }
+
+    let disabled = WriteOptions {
+        disable_wal: true,
+        ..WriteOptions::default()
+    };
+    db.put(&disabled, get_bytes(20), get_bytes(20)).unwrap();
+    assert_eq!(db.get(&get_bytes(20)).unwrap(), Some(get_bytes(20)));
+    drop(db);
+
+    let db = open_db(dir.as_path(), |_| ());
+    assert!(db.recovery_report().unlogged_writes_lost);
+    assert_eq!(db.get(&get_bytes(20)).unwrap(), None);
+    drop(db);
+
+    let db = open_db(dir.as_path(), |_| ());
+    assert!(!db.recovery_report().unlogged_writes_lost);
+}
+
+#[test]
+fn test_point_in_time_recovery() {
+    let (db, dir) = open_temp_db(|_| ());
+    drop(db);
+    let write_options = WriteOptions::default();
+
+    // every open starts a new log, the keys of a round go to the log of the round
+    for round in 0..3 {
+        let db = open_db(dir.as_path(), |_| ());
+
+        for key in round * 100..(round + 1) * 100 {
+            db.put(&write_options, get_bytes(key), get_bytes(key))
+                .unwrap();
+        }
+    }
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(dir.join("log_2"))
+        .unwrap();
+    file.seek(SeekFrom::Start(1000)).unwrap();
+    file.write_all(&[0xff; 8]).unwrap();
+    drop(file);
+
+    let db = open_db(dir.as_path(), |options| {
+        options.wal_recovery_mode = WalRecoveryMode::PointInTimeRecovery;
+    });
+    let report = db.recovery_report();
+
+    assert!(report.stopped_early);
+    assert!(report.dropped_bytes > 0);
+    assert_eq!(report.lost_logs, vec![3]);
+    assert_eq!(
+        LogManager::lost_log_numbers(dir.as_path()).unwrap(),
+        vec![3]
+    );
+    assert!(!dir.join("log_3").exists());
+
+    assert_eq!(db.get(&get_bytes(99)).unwrap(), Some(get_bytes(99)));
+    assert_eq!(db.get(&get_bytes(100)).unwrap(), Some(get_bytes(100)));
+    assert_eq!(db.get(&get_bytes(199)).unwrap(), None);
+    assert_eq!(db.get(&get_bytes(200)).unwrap(), None);
+}
+
+#[test]
+fn test_write_batch() {
+    let (db, dir) = open_temp_db(|_| ());
+    let db = Arc::new(db);
+    let finished = Arc::new(AtomicBool::new(false));
+
+    // a batch is seen entirely or not at all
+    let reader = {
+        let db = db.clone();
+        let finished = finished.clone();
+        thread::spawn(move || {
+            while !finished.load(Ordering::SeqCst) {
+                let session = db.new_session();
+                let batch = random::<u32>() % 400;
+                let found = (0..10)
+                    .filter(|index| {
+                        session
+                            .get(&get_bytes(batch * 10 + index))
+                            .unwrap()
+                            .is_some()
+                    })
+                    .count();
+
+                assert!(found == 0 || found == 10);
+            }
+        })
+    };
+
+    let writers = (0..4)
+        .map(|writer| {
+            let db = db.clone();
+            thread::spawn(move || {
+                for batch_number in (writer..400).step_by(4) {
+                    let mut batch = WriteBatch::new();
+
+                    for index in 0..10 {
+                        let key = batch_number * 10 + index;
+                        batch.put(get_bytes(key), get_bytes(key));
+                    }
+
+                    db.write(&WriteOptions::default(), batch).unwrap();
+                    // visible as soon as the write returns
+                    assert!(db.get(&get_bytes(batch_number * 10)).unwrap().is_some());
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for writer in writers {
+        writer.join().unwrap();
+    }
+
+    finished.store(true, Ordering::SeqCst);
+    reader.join().unwrap();
+
+    let mut batch = WriteBatch::new();
+    batch.delete(get_bytes(0));
+    batch.put(get_bytes(0), get_bytes(1));
+    db.write(&WriteOptions::default(), batch).unwrap();
+
+    assert_eq!(db.get(&get_bytes(0)).unwrap(), Some(get_bytes(1)));
+    for key in 1..4000 {
+        assert_eq!(db.get(&get_bytes(key)).unwrap(), Some(get_bytes(key)));
+    }
+
+    drop(db);
+
+    let db = open_db(dir.as_path(), |_| ());
+    assert_eq!(db.get(&get_bytes(0)).unwrap(), Some(get_bytes(1)));
+    for key in 1..4000 {
+        assert_eq!(db.get(&get_bytes(key)).unwrap(), Some(get_bytes(key)));
+    }
+}
+
+#[test]
+fn test_inplace_callback() {
+    struct Counter;
+
+    impl InplaceCallback for Counter {
+        fn name(&self) -> &str {
+            "counter"
+        }
+
+        fn update(&self, _: &[u8], existing: Option<&mut [u8]>, value: &[u8]) -> InplaceDecision {
+            let delta = u64::from_le_bytes(value.try_into().unwrap());
+
+            match existing {
+                Some(existing) => {
+                    let count = u64::from_le_bytes((&*existing).try_into().unwrap());
+                    existing.copy_from_slice(&(count + delta).to_le_bytes());
+                    InplaceDecision::Updated(8)
+                }
+                None => InplaceDecision::Insert(Bytes::copy_from_slice(value)),
+            }
+        }
+    }
+
+    let configure = |options: &mut Options| {
+        options.inplace_update_support = true;
+        options.inplace_callback = Some(Arc::new(Counter));
+    };
+    let increment = |db: &TestDB, key: u32, delta: u64| {
+        db.put(
+            &WriteOptions::default(),
+            get_bytes(key),
+            Bytes::copy_from_slice(&delta.to_le_bytes()),
+        )
+        .unwrap();
+    };
+    let count = |db: &TestDB, key: u32| {
+        db.get(&get_bytes(key))
+            .unwrap()
+            .map(|value| u64::from_le_bytes(value.as_ref().try_into().unwrap()))
+    };
+
+    let (db, dir) = open_temp_db(configure);
+
+    for delta in 1..=100 {
+        increment(&db, 0, delta);
+    }
+    assert_eq!(count(&db, 0), Some(5050));
+
+    // the next increments merge into the value of the frozen memtable
+    db.core.renew_memtable().unwrap();
+    increment(&db, 0, 10);
+    increment(&db, 1, 3);
+    assert_eq!(count(&db, 0), Some(5060));
+    assert_eq!(count(&db, 1), Some(3));
+
+    db.delete(&WriteOptions::default(), get_bytes(1)).unwrap();
+    increment(&db, 1, 4);
+    assert_eq!(count(&db, 1), Some(4));
+    drop(db);
+
+    let db = open_db(dir.as_path(), configure);
+    assert_eq!(count(&db, 0), Some(5060));
+    assert_eq!(count(&db, 1), Some(4));
+
+    // the concurrent increments and deletions are applied in the order of their sequences, the
+    // replay of the log gives the same counts
+    thread::scope(|scope| {
+        for thread in 0..8 {
+            let db = &db;
+
+            scope.spawn(move || {
+                for round in 0..200 {
+                    if (thread + round) % 50 == 0 {
+                        db.delete(&WriteOptions::default(), get_bytes(2)).unwrap();
+                    } else {
+                        increment(db, 2, 1);
+                    }
+                    increment(db, 3, 1);
+                }
+            });
+        }
+    });
+    assert_eq!(count(&db, 3), Some(1600));
+    let live = count(&db, 2);
+    drop(db);
+
+    let db = open_db(dir.as_path(), configure);
+    assert_eq!(count(&db, 2), live);
+    assert_eq!(count(&db, 3), Some(1600));
+}
+
+#[test]
+fn test_invalid_options() {
+    let mut dir = temp_dir();
+    dir.push(format!("komachi_test_db_{}", random::<u16>()));
+
+    let mut options = Options::new(dir);
+    options.memtable_rep = MemTableRepType::Vector;
+    options.inplace_update_support = true;
+
+    assert!(matches!(
+        TestDB::open(options),
+        Err(Error::InvalidOptions(_))
+    ));
+}
+
+#[test]
+fn test_writes_stopped() {
+    // panics on the second key of the batch, once the first one is in the memtable
+    struct Failing;
+
+    impl InplaceCallback for Failing {
+        fn name(&self) -> &str {
+            "failing"
+        }
+
+        fn update(&self, key: &[u8], _: Option<&mut [u8]>, value: &[u8]) -> InplaceDecision {
+            assert_ne!(key, get_bytes(2).as_ref());
+            InplaceDecision::Insert(Bytes::copy_from_slice(value))
+        }
+    }
+
+    let (db, _) = open_temp_db(|options| {
+        options.inplace_update_support = true;
+        options.inplace_callback = Some(Arc::new(Failing));
+    });
+    let write_options = WriteOptions::default();
+    db.put(&write_options, get_bytes(0), get_bytes(0)).unwrap();
+
+    let mut batch = WriteBatch::new();
+    batch.put(get_bytes(1), get_bytes(1));
+    batch.put(get_bytes(2), get_bytes(2));
+    let failed = thread::scope(|scope| scope.spawn(|| db.write(&write_options, batch)).join());
+    assert!(failed.is_err());
+
+    // the part of the batch in the memtable is never read, and nothing is written after it
+    assert_eq!(db.get(&get_bytes(1)).unwrap(), None);
+    assert!(matches!(
+        db.put(&write_options, get_bytes(3), get_bytes(3)),
+        Err(Error::WritesStopped)
+    ));
+    assert_eq!(db.get(&get_bytes(3)).unwrap(), None);
+    assert_eq!(db.get(&get_bytes(0)).unwrap(), Some(get_bytes(0)));
+}
+
+#[test]
+fn test_delete_range() {
+    let (db, dir) = open_temp_db(|_| ());
+    let write_options = WriteOptions::default();
+
+    for key in 0..100 {
+        db.put(&write_options, get_bytes(key), get_bytes(key))
+            .unwrap();
+    }
+
+    let session = db.new_session();
+
+    // the deletion also covers the versions of the older memtables
+    db.core.renew_memtable().unwrap();
+    db.delete_range(&write_options, get_bytes(10), get_bytes(20))
+        .unwrap();
+    db.put(&write_options, get_bytes(15), get_bytes(0)).unwrap();
+
+    let check = |db: &TestDB| {
+        for key in 0..100 {
+            let expected = match key {
+                15 => Some(get_bytes(0)),
+                10..=19 => None,
+                _ => Some(get_bytes(key)),
+            };
+
+            assert_eq!(db.get(&get_bytes(key)).unwrap(), expected);
+        }
+    };
+
+    check(&db);
+
+    // a session older than the deletion still sees the keys
+    for key in 10..20 {
+        assert_eq!(session.get(&get_bytes(key)).unwrap(), Some(get_bytes(key)));
+    }
+    drop(session);
+
+    let mut updates = db.get_updates_since(100).unwrap();
+    let result = updates.next().unwrap().unwrap();
+    assert_eq!(result.sequence, 100);
+    assert_eq!(result.batch.entries()[0].ty, ValueType::RangeDeletion);
+    assert_eq!(result.batch.entries()[0].value, get_bytes(20));
+    drop(updates);
+
+    drop(db);
+
+    let db = open_db(dir.as_path(), |_| ());
+    check(&db);
+}
+
+#[test]
+fn test_write_buffer_manager() {
+    let manager = Arc::new(WriteBufferManager::new(256 * 1024));
+    let open = || open_temp_db(|options| options.write_buffer_manager = Some(manager.clone())).0;
+
+    let first = open();
+    let second = open();
+    let write_options = WriteOptions::default();
+    let value = Bytes::from(vec![0u8; 1024]);
+
+    for key in 0..150 {
+        second
+            .put(&write_options, get_bytes(key), value.clone())
+            .unwrap();
+    }
+
+    // the flush of the largest memtable is requested, the idle database does it on its next write
+    for key in 0..100 {
+        first
+            .put(&write_options, get_bytes(key), value.clone())
+            .unwrap();
+    }
+    assert_eq!(manager.memory_usage(), manager.mutable_memory_usage());
+
+    second
+        .put(&write_options, get_bytes(150), value.clone())
+        .unwrap();
+    assert!(manager.memory_usage() - manager.mutable_memory_usage() > 150 * 1024);
+
+    // over the budget, the writing database keeps flushing its own memtable
+    for key in 100..1000 {
+        first
+            .put(&write_options, get_bytes(key), value.clone())
+            .unwrap();
+    }
+    assert!(manager.mutable_memory_usage() <= 256 * 1024);
+    assert!(manager.memory_usage() > 1000 * 1024);
+
+    for key in 0..1000 {
+        assert_eq!(first.get(&get_bytes(key)).unwrap(), Some(value.clone()));
+    }
+
+    let usage = manager.memory_usage();
+    drop(second);
+    assert!(manager.memory_usage() < usage - 150 * 1024);
+}
+
+#[test]
+fn test_write_stall() {
+    let mut manager = WriteBufferManager::new(256 * 1024);
+    manager.set_stall_limit(1024 * 1024);
+    let manager = Arc::new(manager);
+    let open = || open_temp_db(|options| options.write_buffer_manager = Some(manager.clone())).0;
+
+    let first = open();
+    let second = open();
+    let value = Bytes::from(vec![0u8; 1024]);
+    let no_slowdown = WriteOptions {
+        no_slowdown: true,
+        ..WriteOptions::default()
+    };
+
+    let stalled = (0..10000).find_map(|key| {
+        second
+            .put(&no_slowdown, get_bytes(key), value.clone())
+            .err()
+    });
+    assert!(matches!(stalled, Some(Error::WriteStall)));
+    assert!(manager.is_stalled());
+
+    // the write waits until the memory of the closed database is released
+    let waiting = {
+        let value = value.clone();
+
+        thread::spawn(move || {
+            first
+                .put(&WriteOptions::default(), get_bytes(0), value)
+                .unwrap();
+            first
+        })
+    };
+    thread::sleep(Duration::from_millis(50));
+    assert!(!waiting.is_finished());
+
+    drop(second);
+    let first = waiting.join().unwrap();
+    assert!(!manager.is_stalled());
+    assert_eq!(first.get(&get_bytes(0)).unwrap(), Some(value.clone()));
+
+    // the background compaction of the frozen memtables releases the overwritten versions, so
+    // three times the stall limit is written without stalling for good
+    for _ in 0..300 {
+        for key in 0..10 {
+            first
+                .put(&WriteOptions::default(), get_bytes(key), value.clone())
+                .unwrap();
+        }
+    }
+    first.core.wait_for_compactions();
+    assert!(manager.memory_usage() < 1024 * 1024);
+}
+
+#[test]
+fn test_rate_limiter_backlog() {
+    // records the rate while the memtable is compacted
+    struct RateFilter(Arc<RateLimiter>, Mutex<Vec<u64>>);
+
+    impl CompactionFilter for RateFilter {
+        fn name(&self) -> &str {
+            "RateFilter"
+        }
+
+        fn filter(&self, _: usize, _: &[u8], _: &[u8], _: ValueTag) -> CompactionDecision {
+            self.1.lock().unwrap().push(self.0.bytes_per_second());
+            CompactionDecision::Keep
+        }
+    }
+
+    let min_rate = 64 * 1024 * 1024;
+    let rate_limiter = Arc::new(RateLimiter::auto_tuned(
+        min_rate,
+        1024 * 1024 * 1024,
+        1024 * 1024,
+    ));
+    let filter = Arc::new(RateFilter(rate_limiter.clone(), Mutex::new(vec![])));
+    let (db, _) = open_temp_db(|options| {
+        options.rate_limiter = Some(rate_limiter.clone());
+        options.compaction_filter = Some(filter.clone());
+    });
+
+    for key in 0..200 {
+        db.put(
+            &WriteOptions::default(),
+            get_bytes(key),
+            Bytes::from(vec![0u8; 1024]),
+        )
+        .unwrap();
+    }
+    assert_eq!(rate_limiter.bytes_per_second(), min_rate);
+
+    // the rate is raised while the frozen memtable waits for its compaction
+    db.core.renew_memtable().unwrap();
+    db.core.wait_for_compactions();
+
+    let rates = filter.1.lock().unwrap();
+    assert_eq!(rates.len(), 200);
+    assert!(rates.iter().all(|rate| *rate > min_rate));
+    assert_eq!(rate_limiter.bytes_per_second(), min_rate);
+}
+
+#[test]
+fn test_estimate_range() {
+    let (db, _) = open_temp_db(|_| ());
+    let write_options = WriteOptions::default();
+
+    for key in 0..1000 {
+        db.put(&write_options, get_bytes(key), get_bytes(key))
+            .unwrap();
+    }
+
+    db.core.renew_memtable().unwrap();
+
+    // the versions of every memtable are counted
+    for key in 0..10 {
+        db.put(&write_options, get_bytes(key), get_bytes(key))
+            .unwrap();
+    }
+
+    let estimate = db.estimate_range(&get_bytes(5), &get_bytes(15));
+    assert_eq!(estimate.count, 15);
+}
+
+#[test]
+fn test_compact_on_renew() {
+    let (db, _) = open_temp_db(|_| ());
+    let write_options = WriteOptions::default();
+
+    for value in 1..=10 {
+        db.put(&write_options, get_bytes(0), get_bytes(value))
+            .unwrap();
+    }
+    let session = db.new_session();
+
+    for value in 11..=20 {
+        db.put(&write_options, get_bytes(0), get_bytes(value))
+            .unwrap();
+    }
+
+    // only the versions the session and the newest reads see are kept
+    db.core.renew_memtable().unwrap();
+    db.core.wait_for_compactions();
+    assert_eq!(db.estimate_range(&get_bytes(0), &get_bytes(1)).count, 2);
+    assert_eq!(session.get(&get_bytes(0)).unwrap(), Some(get_bytes(10)));
+    assert_eq!(db.get(&get_bytes(0)).unwrap(), Some(get_bytes(20)));
+}
+
+#[test]
+fn test_log_in_sequence_order() {
+    let (db, _) = open_temp_db(|_| ());
+
+    thread::scope(|scope| {
+        for thread in 0..8 {
+            let db = &db;
+
+            scope.spawn(move || {
+                for key in 0..200 {
+                    let mut batch = WriteBatch::new();
+                    batch.put(get_bytes(thread * 1000 + key), get_bytes(key));
+                    batch.put(get_bytes(thread * 1000 + key + 500), get_bytes(key));
+                    db.write(&WriteOptions::default(), batch).unwrap();
+                }
+            });
+        }
+    });
+
+    let log_manager = db.core.log_manager();
+    let sequences = log_manager
+        .log_iterator(log_manager.current_log_number())
+        .unwrap()
+        .map(|wrapper| {
+            let (tag, _) = InternalKey::split(wrapper.unwrap().record().key());
+            tag.sequence_number
+        })
+        .collect::<Vec<_>>();
+
+    // every write is logged, in the order of its sequences
+    assert_eq!(sequences.len(), 8 * 200 * 2);
+    assert!(sequences.windows(2).all(|pair| pair[0] + 1 == pair[1]));
+}
+
+#[test]
+fn test_updates_with_concurrent_writes() {
+    let (db, _) = open_temp_db(|_| ());
+    let mut updates = db.get_updates_since(0).unwrap();
+    let writes = 8 * 200;
+
+    let results = thread::scope(|scope| {
+        for thread in 0..8 {
+            let db = &db;
+
+            scope.spawn(move || {
+                for key in 0..200 {
+                    let mut batch = WriteBatch::new();
+                    batch.put(get_bytes(thread * 1000 + key), get_bytes(key));
+                    batch.delete(get_bytes(thread * 1000 + key + 500));
+                    db.write(&WriteOptions::default(), batch).unwrap();
+                }
+            });
+        }
+
+        // read while the writes come
+        let mut results = vec![];
+
+        while results.len() < writes {
+            match updates.wait_next(Duration::from_secs(10)) {
+                Some(result) => results.push(result.unwrap()),
+                None => break,
+            }
+        }
+
+        results
+    });
+
+    // every write is returned once, in the order of its sequences
+    assert_eq!(results.len(), writes);
+    for (index, result) in results.iter().enumerate() {
+        assert_eq!(result.sequence, index as u64 * 2);
+        assert_eq!(result.batch.len(), 2);
+    }
+    assert!(updates.next().is_none());
+}
+
+#[test]
+fn test_point_in_time_recovery_with_concurrent_writes() {
+    let (db, dir) = open_temp_db(|_| ());
+
+    thread::scope(|scope| {
+        for thread in 0..8 {
+            let db = &db;
+
+            scope.spawn(move || {
+                for key in 0..100 {
+                    db.put(
+                        &WriteOptions::default(),
+                        get_bytes(thread * 1000 + key),
+                        get_bytes(key),
+                    )
+                    .unwrap();
+                }
+            });
+        }
+    });
+
+    let log_manager = db.core.log_manager();
+    let mut keys = log_manager
+        .log_iterator(log_manager.current_log_number())
+        .unwrap()
+        .map(|wrapper| {
+            let wrapper = wrapper.unwrap();
+            let record = wrapper.record();
+            let (tag, key) = InternalKey::split(record.key());
+            (
+                tag.sequence_number,
+                u32::from_ne_bytes(key.try_into().unwrap()),
+            )
+        })
+        .collect::<Vec<_>>();
+    keys.sort_unstable();
+    drop(db);
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(dir.join("log_0"))
+        .unwrap();
+    file.seek(SeekFrom::Start(5000)).unwrap();
+    file.write_all(&[0xff; 8]).unwrap();
+    drop(file);
+
+    let db = open_db(dir.as_path(), |options| {
+        options.wal_recovery_mode = WalRecoveryMode::PointInTimeRecovery;
+    });
+    assert!(db.recovery_report().stopped_early);
+
+    // the recovered writes are exactly the ones below a sequence
+    let recovered = keys
+        .iter()
+        .map(|(_, key)| db.get(&get_bytes(*key)).unwrap().is_some())
+        .collect::<Vec<_>>();
+    let kept = recovered.iter().take_while(|recovered| **recovered).count();
+
+    assert!(kept > 0 && kept < keys.len());
+    assert!(recovered[kept..].iter().all(|recovered| !recovered));
+}
@@ -4,7 +4,7 @@ use thiserror::Error;
 pub type Result<T> = StdResult<T, Error>;
 
 #[allow(dead_code)]
-#[derive(Error, Debug)]
+#[derive(Error, Debug, Clone)]
 pub enum Error {
     #[error("Sequence number overflow")]
     SequenceNumberOverflow,
@@ -12,6 +12,8 @@ pub enum Error {
     UnableToCreateFile(String),
     #[error("Unable to read dir \"{0}\"")]
     UnableToReadDir(String),
+    #[error("Unable to sync dir \"{0}\"")]
+    UnableToSyncDir(String),
     #[error("Unable to truncate log file: \"{0}\"")]
     UnableToTruncateLogFile(String),
     #[error("Unable to write log file: \"{0}\"")]
@@ -20,6 +22,34 @@ pub enum Error {
     UnableToReadLogFile(String),
     #[error("Unexpected chunk CRC code at file: \"{0}\"")]
     UnexpectedChunkCRC(String),
+    #[error("Corrupted log header at file: \"{0}\"")]
+    CorruptedLogHeader(String),
+    #[error("Unsupported log format version {1} at file: \"{0}\"")]
+    UnsupportedLogFormat(String, u32),
+    #[error("Log file \"{0}\" was written with comparator \"{1}\"")]
+    ComparatorMismatch(String, String),
+    #[error("Log file \"{0}\" belongs to log number {1}")]
+    UnexpectedLogNumber(String, u64),
+    #[error("Invalid chunk")]
+    InvalidChunk,
+    #[error("Corrupted log record at file: \"{0}\"")]
+    CorruptedLogRecord(String),
+    #[error("Incomplete log record at the end of file: \"{0}\"")]
+    IncompleteLogRecord(String),
+    #[error("Unknown compression type {0}")]
+    UnknownCompressionType(u8),
+    #[error("Unable to decompress {0:?} data")]
+    UnableToDecompress(crate::format::CompressionType),
+    #[error("No log found after log number {0}")]
+    MissingLog(u64),
+    #[error("The leader of the write group panicked")]
+    GroupCommitAborted,
+    #[error("Writes are stopped since a write failed after reaching the memtable")]
+    WritesStopped,
+    #[error("Write stalled by the write buffer manager")]
+    WriteStall,
+    #[error("Invalid options: {0}")]
+    InvalidOptions(String),
 }
 
 #[macro_export]
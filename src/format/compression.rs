@@ -0,0 +1,56 @@
+use crate::error::{Error, Result};
+use std::convert::TryFrom;
+
+/// How the value of a log record is compressed, it is written in front of every record.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CompressionType {
+    NoCompression = 0,
+    Snappy = 1,
+}
+
+impl CompressionType {
+    /// Returns `None` when the data is left uncompressed.
+    pub fn compress(&self, data: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            CompressionType::NoCompression => None,
+            CompressionType::Snappy => snap::raw::Encoder::new().compress_vec(data).ok(),
+        }
+    }
+
+    pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            CompressionType::NoCompression => Ok(data.to_vec()),
+            CompressionType::Snappy => snap::raw::Decoder::new()
+                .decompress_vec(data)
+                .map_err(|_| Error::UnableToDecompress(*self)),
+        }
+    }
+}
+
+impl TryFrom<u8> for CompressionType {
+    type Error = Error;
+
+    fn try_from(byte: u8) -> Result<Self> {
+        match byte {
+            0 => Ok(CompressionType::NoCompression),
+            1 => Ok(CompressionType::Snappy),
+            _ => Err(Error::UnknownCompressionType(byte)),
+        }
+    }
+}
+
+#[test]
+fn test_compression() {
+    let data = vec![7u8; 4096];
+
+    assert!(CompressionType::NoCompression.compress(&data).is_none());
+
+    let compressed = CompressionType::Snappy.compress(&data).unwrap();
+    assert!(compressed.len() < data.len());
+    assert_eq!(
+        CompressionType::Snappy.decompress(&compressed).unwrap(),
+        data
+    );
+    assert!(CompressionType::Snappy.decompress(&[0xff; 8]).is_err());
+    assert!(CompressionType::try_from(2).is_err());
+}
@@ -1,5 +1,7 @@
+mod compression;
 mod value_tag;
 mod variable_number;
 
-pub use value_tag::{ValueTag, ValueType, WrappedValueTag};
+pub use compression::CompressionType;
+pub use value_tag::{SequenceNumber, ValueTag, ValueType, WrappedValueTag};
 pub use variable_number::*;
@@ -5,11 +5,18 @@ use std::mem::size_of;
 pub type SequenceNumber = u64;
 pub type WrappedValueTag = [u8; 8];
 
+// the two highest bits of a wrapped tag hold its type
+const TOMBSTONE_FLAG: u64 = 1u64 << (size_of::<u64>() * 8 - 1);
+const RANGE_DELETION_FLAG: u64 = 1u64 << (size_of::<u64>() * 8 - 2);
+const SEQUENCE_MASK: u64 = !(TOMBSTONE_FLAG | RANGE_DELETION_FLAG);
+
 #[allow(dead_code)]
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum ValueType {
     Value = 0,
     TombStone = 1,
+    // the key is the beginning of the deleted range, the value its exclusive end
+    RangeDeletion = 2,
 }
 
 #[allow(dead_code)]
@@ -22,7 +29,10 @@ pub struct ValueTag {
 #[allow(dead_code)]
 impl ValueTag {
     pub fn new(sequence_number: SequenceNumber, ty: ValueType) -> Result<ValueTag> {
-        assert_as_error!(sequence_number < u64::MAX, Error::SequenceNumberOverflow);
+        assert_as_error!(
+            sequence_number < SEQUENCE_MASK,
+            Error::SequenceNumberOverflow
+        );
 
         Ok(ValueTag {
             sequence_number,
@@ -37,27 +47,39 @@ impl ValueTag {
     pub fn is_tombstone(&self) -> bool {
         self.ty == ValueType::TombStone
     }
+
+    pub fn is_range_deletion(&self) -> bool {
+        self.ty == ValueType::RangeDeletion
+    }
+
+    /// Decodes a tag of a legacy log, written in native byte order.
+    pub fn from_legacy(wrapped_tag: WrappedValueTag) -> Self {
+        Self::from(u64::from_ne_bytes(wrapped_tag).to_le_bytes())
+    }
 }
 
 impl Into<WrappedValueTag> for ValueTag {
     fn into(self) -> WrappedValueTag {
         let num: u64 = match self.ty {
-            ValueType::Value => self.sequence_number & !(1u64 << (size_of::<u64>() * 8 - 1)),
-            ValueType::TombStone => self.sequence_number | 1u64 << (size_of::<u64>() * 8 - 1),
+            ValueType::Value => self.sequence_number & SEQUENCE_MASK,
+            ValueType::TombStone => self.sequence_number & SEQUENCE_MASK | TOMBSTONE_FLAG,
+            ValueType::RangeDeletion => self.sequence_number & SEQUENCE_MASK | RANGE_DELETION_FLAG,
         };
 
-        num.to_ne_bytes()
+        num.to_le_bytes()
     }
 }
 
 impl From<WrappedValueTag> for ValueTag {
     fn from(wrapped_tag: WrappedValueTag) -> Self {
-        let num = u64::from_ne_bytes(wrapped_tag);
-        let sequence_number = num & !(1u64 << (size_of::<u64>() * 8 - 1));
-        let ty = if (num & 1u64 << (size_of::<u64>() * 8 - 1)) == 0 {
-            ValueType::Value
-        } else {
+        let num = u64::from_le_bytes(wrapped_tag);
+        let sequence_number = num & SEQUENCE_MASK;
+        let ty = if num & TOMBSTONE_FLAG != 0 {
             ValueType::TombStone
+        } else if num & RANGE_DELETION_FLAG != 0 {
+            ValueType::RangeDeletion
+        } else {
+            ValueType::Value
         };
 
         ValueTag {
@@ -69,11 +91,19 @@ impl From<WrappedValueTag> for ValueTag {
 
 impl From<*const u8> for ValueTag {
     fn from(ptr: *const u8) -> Self {
-        let slice = unsafe { *(ptr as *const u64) }.to_ne_bytes();
+        let slice = unsafe { (ptr as *const WrappedValueTag).read() };
         Self::from(slice)
     }
 }
 
+#[test]
+fn test_legacy_tag() {
+    let wrapped = (12345 | TOMBSTONE_FLAG).to_ne_bytes();
+    let result = ValueTag::from_legacy(wrapped);
+    assert_eq!(result.sequence_number, 12345);
+    assert_eq!(result.ty, ValueType::TombStone);
+}
+
 #[test]
 fn test_value() {
     use rand::random;
@@ -95,3 +125,14 @@ fn test_tombstone() {
     assert_eq!(result.sequence_number, sequence);
     assert_eq!(result.ty, ValueType::TombStone);
 }
+
+#[test]
+fn test_range_deletion() {
+    use rand::random;
+    let sequence = random::<u32>() as u64;
+    let raw = ValueTag::new(sequence, ValueType::RangeDeletion).unwrap();
+    let wrapped: WrappedValueTag = raw.into();
+    let result: ValueTag = wrapped.into();
+    assert_eq!(result.sequence_number, sequence);
+    assert_eq!(result.ty, ValueType::RangeDeletion);
+}
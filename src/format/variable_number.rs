@@ -38,6 +38,28 @@ pub fn decode_usize(ptr: *const u8) -> (usize, *const u8) {
     )
 }
 
+/// Decodes the number at the beginning of `slice` without reading past it, returns the number
+/// and the size of its encoding, or `None` if the encoding is truncated or too long.
+pub fn decode_usize_checked(slice: &[u8]) -> Option<(usize, usize)> {
+    let mut num: usize = 0;
+
+    for (index, byte) in slice.iter().enumerate() {
+        let shift = 7 * index as u32;
+
+        if shift >= usize::BITS {
+            return None;
+        }
+
+        num |= ((byte & !(1 << 7)) as usize) << shift;
+
+        if (byte & (1 << 7)) == 0 {
+            return Some((num, index + 1));
+        }
+    }
+
+    None
+}
+
 #[test]
 fn test_encode() {
     use rand::random;
@@ -46,4 +68,6 @@ fn test_encode() {
     let result = decode_usize(a.as_ref().as_ptr());
 
     assert_eq!(result.0, num);
+    assert_eq!(decode_usize_checked(a.as_ref()), Some((num, a.len())));
+    assert_eq!(decode_usize_checked(&a[..a.len() - 1]), None);
 }
@@ -0,0 +1,23 @@
+use crate::format::ValueTag;
+use bytes::Bytes;
+
+pub enum CompactionDecision {
+    Keep,
+    Remove,
+    ChangeValue(Bytes),
+}
+
+pub trait CompactionFilter: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Called with the newest visible version of every key that is rewritten by a compaction.
+    /// `Remove` turns the entry into a tombstone at the same sequence number, so older versions
+    /// of the key do not resurface.
+    fn filter(
+        &self,
+        level: usize,
+        key: &[u8],
+        value: &[u8],
+        value_tag: ValueTag,
+    ) -> CompactionDecision;
+}
@@ -2,15 +2,37 @@ use std::cmp::Ordering;
 use std::fmt::Display;
 use std::marker::PhantomData;
 
-pub trait Comparator: Sync {
+pub trait Comparator: Sync + 'static {
     fn compare(a: &[u8], b: &[u8]) -> Ordering;
+
+    /// Recorded in every log file, a log can only be read with the comparator that wrote it. The
+    /// name must stay the same for as long as logs written with the comparator exist.
+    fn name() -> &'static str;
+}
+
+/// A number ordered by `NumberComparator`, with the name its comparator records in the logs.
+pub trait ComparableNumber: 'static + Sized + Ord + Copy + Display + Sync {
+    const COMPARATOR_NAME: &'static str;
+}
+
+macro_rules! comparable_numbers {
+    ($($ty: ty),*) => {
+        $(
+            impl ComparableNumber for $ty {
+                const COMPARATOR_NAME: &'static str =
+                    concat!("komachi.NumberComparator.", stringify!($ty));
+            }
+        )*
+    };
 }
 
-pub struct NumberComparator<T: 'static + Sized + Ord + Copy + Display + Sync>(PhantomData<T>);
+comparable_numbers!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
 
-unsafe impl<T: 'static + Sized + Ord + Copy + Display + Sync> Sync for NumberComparator<T> {}
+pub struct NumberComparator<T: ComparableNumber>(PhantomData<T>);
 
-impl<T: 'static + Sized + Ord + Copy + Display + Sync> Comparator for NumberComparator<T> {
+unsafe impl<T: ComparableNumber> Sync for NumberComparator<T> {}
+
+impl<T: ComparableNumber> Comparator for NumberComparator<T> {
     fn compare(a: &[u8], b: &[u8]) -> Ordering {
         let a_ref = unsafe { (a.as_ptr() as *const T).as_ref().unwrap() };
 
@@ -18,4 +40,20 @@ impl<T: 'static + Sized + Ord + Copy + Display + Sync> Comparator for NumberComp
 
         a_ref.cmp(b_ref)
     }
+
+    fn name() -> &'static str {
+        T::COMPARATOR_NAME
+    }
+}
+
+#[test]
+fn test_number_comparator_name() {
+    assert_eq!(
+        NumberComparator::<u32>::name(),
+        "komachi.NumberComparator.u32"
+    );
+    assert_eq!(
+        NumberComparator::<i64>::name(),
+        "komachi.NumberComparator.i64"
+    );
 }
@@ -0,0 +1,20 @@
+use bytes::Bytes;
+
+pub enum InplaceDecision {
+    /// The existing value was updated in place, it keeps its first `usize` bytes, which must not
+    /// be more than it had.
+    Updated(usize),
+    /// The value is added as a new version of the key.
+    Insert(Bytes),
+    /// The write is dropped.
+    Skip,
+}
+
+pub trait InplaceCallback: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Merges the value of a put into the newest version of the key, `existing` is `None` when
+    /// the key has no value. `existing` may be modified in place whatever the decision is, only
+    /// `Updated` keeps the changes.
+    fn update(&self, key: &[u8], existing: Option<&mut [u8]>, value: &[u8]) -> InplaceDecision;
+}
@@ -1,3 +1,7 @@
+mod compaction_filter;
 mod comparator;
+mod inplace_callback;
 
-pub use comparator::{Comparator, NumberComparator};
+pub use compaction_filter::{CompactionDecision, CompactionFilter};
+pub use comparator::{ComparableNumber, Comparator, NumberComparator};
+pub use inplace_callback::{InplaceCallback, InplaceDecision};
@@ -5,6 +5,8 @@
 
 #[macro_use]
 mod error;
+mod collector;
+mod compaction;
 mod core;
 mod db;
 mod format;
@@ -12,10 +14,29 @@ mod helper;
 mod interface;
 mod logger;
 mod memtable;
+mod options;
+mod rate_limiter;
 mod session;
 mod skip_list;
+mod update_iterator;
+mod write_batch;
+mod write_buffer_manager;
 
 pub use db::KomachiDB;
+pub use error::{Error, Result};
+pub use format::{CompressionType, SequenceNumber, ValueTag, ValueType};
 pub use interface::*;
+pub use logger::RecoveryReport;
+pub use options::{
+    LevelGeneratorType, MemTableBloomFilter, MemTableRepType, Options, WalRecoveryMode,
+    WalSyncMode, WriteOptions,
+};
+pub use rate_limiter::{IOPriority, RateLimiter};
 pub use session::Session;
-pub use skip_list::LevelGenerator;
+pub use skip_list::{
+    max_level_for_entries, LevelGenerator, RandomLevelGenerator, RangeEstimate,
+    SeededLevelGenerator, XorShiftLevelGenerator,
+};
+pub use update_iterator::{BatchResult, UpdateIterator};
+pub use write_batch::{BatchEntry, WriteBatch};
+pub use write_buffer_manager::WriteBufferManager;
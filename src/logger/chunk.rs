@@ -1,13 +1,48 @@
+use crate::error::Error;
+use crate::logger::file_header::{LEGACY_FORMAT_VERSION, LOG_FORMAT_VERSION};
+use crate::logger::LogNumber;
 use crc32fast::Hasher;
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
 use std::mem::size_of;
 
-pub const CHUNK_HEAD_SIZE: usize = size_of::<u8>() // ty
+pub const LEGACY_CHUNK_HEAD_SIZE: usize = size_of::<u8>() // ty
     + size_of::<u32>() // crc
     + size_of::<u16>();
 
+// the low 32 bits of the log number follow in the current format, so the chunks of the previous
+// log of a reused file are told apart from corrupted ones
+pub const CHUNK_HEAD_SIZE: usize = LEGACY_CHUNK_HEAD_SIZE + size_of::<u32>();
+
 pub const MIN_CHUNK_SIZE: usize = CHUNK_HEAD_SIZE * 2;
 
+pub fn chunk_head_size(format_version: u32) -> usize {
+    if format_version == LEGACY_FORMAT_VERSION {
+        LEGACY_CHUNK_HEAD_SIZE
+    } else {
+        CHUNK_HEAD_SIZE
+    }
+}
+
+/// The rest of a block is padding when it is shorter than this.
+pub fn min_chunk_size(format_version: u32) -> usize {
+    chunk_head_size(format_version) * 2
+}
+
+/// Reads the log number from the head of a chunk of the current format, whatever the rest of
+/// the chunk holds. `None` when `bytes` is too short.
+pub fn chunk_log_number(bytes: &[u8]) -> Option<u32> {
+    bytes
+        .get(LEGACY_CHUNK_HEAD_SIZE..CHUNK_HEAD_SIZE)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+const CRC_MASK_DELTA: u32 = 0xa282_ead8;
+
+// a crc computed over data containing crcs is weak, the stored crc is rotated and offset
+fn mask_crc(crc: u32) -> u32 {
+    crc.rotate_right(15).wrapping_add(CRC_MASK_DELTA)
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum ChunkType {
     Full,
@@ -33,14 +68,16 @@ impl Into<u8> for ChunkType {
     }
 }
 
-impl From<&u8> for ChunkType {
-    fn from(byte: &u8) -> Self {
+impl TryFrom<&u8> for ChunkType {
+    type Error = Error;
+
+    fn try_from(byte: &u8) -> Result<Self, Self::Error> {
         match byte {
-            0 => ChunkType::Full,
-            1 => ChunkType::First,
-            2 => ChunkType::Middle,
-            3 => ChunkType::Last,
-            _ => panic!("Unexpected chunk type"),
+            0 => Ok(ChunkType::Full),
+            1 => Ok(ChunkType::First),
+            2 => Ok(ChunkType::Middle),
+            3 => Ok(ChunkType::Last),
+            _ => Err(Error::InvalidChunk),
         }
     }
 }
@@ -50,49 +87,69 @@ pub struct Chunk<'a> {
     ty: [u8; 1],
     data_size: [u8; 2],
     crc32: [u8; 4],
+    // empty in the legacy format
+    log_number: Vec<u8>,
+    // decides what the checksum covers
+    format_version: u32,
 }
 
 #[allow(dead_code)]
 impl<'a> Chunk<'a> {
-    pub fn new(data: Vec<&'a [u8]>, ty: ChunkType) -> Self {
-        let mut hasher = Hasher::new();
-        for item in data.clone() {
-            hasher.update(item);
-        }
-
+    /// `log_number` is the log the chunk is written to, its checksum is salted with it.
+    pub fn new(data: Vec<&'a [u8]>, ty: ChunkType, log_number: LogNumber) -> Self {
         let data_size = data.iter().fold(0, |carry, item| carry + item.len()) as u16;
 
-        Chunk {
+        let mut chunk = Chunk {
             ty: [ty.into()],
-            crc32: hasher.finalize().to_ne_bytes(),
-            data_size: data_size.to_ne_bytes(),
+            crc32: [0; 4],
+            data_size: data_size.to_le_bytes(),
             data,
-        }
+            log_number: (log_number as u32).to_le_bytes().to_vec(),
+            format_version: LOG_FORMAT_VERSION,
+        };
+        chunk.crc32 = chunk.checksum(log_number).to_le_bytes();
+
+        chunk
     }
 
-    pub fn check_crc32(&self) -> bool {
+    fn checksum(&self, log_number: LogNumber) -> u32 {
         let mut hasher = Hasher::new();
-        for item in self.data.clone() {
-            hasher.update(item);
+
+        // the legacy checksum covers the data alone
+        if self.format_version == LEGACY_FORMAT_VERSION {
+            self.data.iter().for_each(|item| hasher.update(item));
+
+            return hasher.finalize();
         }
 
-        hasher.finalize() == u32::from_ne_bytes(self.crc32)
+        hasher.update(log_number.to_le_bytes().as_slice());
+        hasher.update(self.ty.as_slice());
+        hasher.update(self.data_size.as_slice());
+        self.data.iter().for_each(|item| hasher.update(item));
+
+        mask_crc(hasher.finalize())
+    }
+
+    /// `log_number` is the log the chunk is read from, the legacy chunks are not salted.
+    pub fn check_crc32(&self, log_number: LogNumber) -> bool {
+        self.checksum(log_number) == u32::from_le_bytes(self.crc32)
     }
 
     pub fn len(&self) -> usize {
-        self.data_len() as usize + CHUNK_HEAD_SIZE // size
+        self.data_len() as usize + chunk_head_size(self.format_version)
     }
 
     pub fn data_len(&self) -> u16 {
-        u16::from_ne_bytes(self.data_size)
+        u16::from_le_bytes(self.data_size)
     }
 
     pub fn ty(&self) -> ChunkType {
-        ChunkType::from(&self.ty[0])
+        // validated when the chunk is created
+        ChunkType::try_from(&self.ty[0]).unwrap()
     }
 
     pub fn crc32(&self) -> u32 {
-        u32::from_ne_bytes(self.crc32)
+        u32::from_le_bytes(self.crc32)
     }
 }
 
@@ -108,6 +165,7 @@ impl<'a, 'b> Into<Vec<&'b [u8]>> for &'b Chunk<'a> {
             self.crc32.as_slice(),
             self.data_size.as_slice(),
             self.ty.as_slice(),
+            self.log_number.as_slice(),
         ];
 
         slices.extend(self.data.clone());
@@ -116,29 +174,54 @@ impl<'a, 'b> Into<Vec<&'b [u8]>> for &'b Chunk<'a> {
     }
 }
 
-impl<'a> From<&'a [u8]> for Chunk<'a> {
-    fn from(bytes: &'a [u8]) -> Self {
-        assert!(bytes.len() >= MIN_CHUNK_SIZE);
+impl<'a> Chunk<'a> {
+    /// Decodes a chunk written in `format_version`, the result is always in the current format.
+    pub fn decode(bytes: &'a [u8], format_version: u32) -> Result<Self, Error> {
+        if bytes.len() < chunk_head_size(format_version) {
+            return Err(Error::InvalidChunk);
+        }
+
         let (crc_bytes, crc_right) = bytes.split_at(size_of::<u32>());
-        let crc32 = crc_bytes.try_into().unwrap();
         let (size_bytes, size_right) = crc_right.split_at(size_of::<u16>());
-        let size: [u8; 2] = size_bytes.try_into().unwrap();
-        let (ty_byte, data_right) = size_right.split_at(size_of::<u8>());
-        if u16::from_ne_bytes(size) as usize > data_right.len() {
-            panic!(format!(
-                "data_right: {}, size: {}",
-                data_right.len(),
-                u16::from_ne_bytes(size)
-            ))
+        let (ty_byte, ty_right) = size_right.split_at(size_of::<u8>());
+        let (log_number, data_right) =
+            ty_right.split_at(chunk_head_size(format_version) - LEGACY_CHUNK_HEAD_SIZE);
+
+        let (crc32, size) = match format_version {
+            LEGACY_FORMAT_VERSION => (
+                u32::from_ne_bytes(crc_bytes.try_into().unwrap()),
+                u16::from_ne_bytes(size_bytes.try_into().unwrap()),
+            ),
+            LOG_FORMAT_VERSION => (
+                u32::from_le_bytes(crc_bytes.try_into().unwrap()),
+                u16::from_le_bytes(size_bytes.try_into().unwrap()),
+            ),
+            _ => return Err(Error::InvalidChunk),
+        };
+
+        if size as usize > data_right.len() {
+            return Err(Error::InvalidChunk);
         }
-        let data = data_right.split_at(u16::from_ne_bytes(size) as usize).0;
-        let ty = ty_byte.try_into().unwrap();
 
-        Chunk {
-            ty,
-            crc32,
+        ChunkType::try_from(&ty_byte[0])?;
+
+        let data = data_right.split_at(size as usize).0;
+
+        Ok(Chunk {
+            ty: ty_byte.try_into().unwrap(),
+            crc32: crc32.to_le_bytes(),
             data: vec![data],
-            data_size: (data.len() as u16).to_ne_bytes(),
-        }
+            data_size: size.to_le_bytes(),
+            log_number: log_number.to_vec(),
+            format_version,
+        })
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for Chunk<'a> {
+    type Error = Error;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        Self::decode(bytes, LOG_FORMAT_VERSION)
     }
 }
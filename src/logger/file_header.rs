@@ -0,0 +1,128 @@
+use crate::error::{Error, Result};
+use crate::logger::LogNumber;
+use crc32fast::Hasher;
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use std::mem::size_of;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const LOG_MAGIC: [u8; 8] = *b"KOMACHI\0";
+
+/// Logs written before the file header existed, in native byte order.
+pub const LEGACY_FORMAT_VERSION: u32 = 0;
+/// Little-endian chunks following a `LogFileHeader`. The masked checksum of a chunk covers its
+/// type and size besides its data and is salted with the log number, so the chunks left in a
+/// reused file by an older log are never valid. Every record starts with a byte holding the
+/// `CompressionType` of its value and whether the next record belongs to the same write.
+pub const LOG_FORMAT_VERSION: u32 = 1;
+
+// the chunks of the previous log follow the last record of this one, the first chunk holding
+// another log number marks the end of the log
+const RECYCLED_FLAG: u8 = 1;
+
+const FIXED_HEADER_SIZE: usize = LOG_MAGIC.len()
+    + size_of::<u32>() // format version
+    + size_of::<u64>() // log number
+    + size_of::<u64>() // creation time
+    + size_of::<u16>(); // comparator name size
+
+const FLAGS_SIZE: usize = size_of::<u8>();
+
+/// Written in little-endian at the beginning of every log file, blocks start right after it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LogFileHeader {
+    pub format_version: u32,
+    pub log_number: LogNumber,
+    /// Seconds since the unix epoch.
+    pub creation_time: u64,
+    pub comparator_name: String,
+    /// Set when the file was reused from an older log.
+    pub recycled: bool,
+}
+
+impl LogFileHeader {
+    pub fn new(log_number: LogNumber, comparator_name: &str) -> Self {
+        LogFileHeader {
+            format_version: LOG_FORMAT_VERSION,
+            log_number,
+            creation_time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or(0),
+            comparator_name: comparator_name.to_string(),
+            recycled: false,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = LOG_MAGIC.to_vec();
+        bytes.extend_from_slice(self.format_version.to_le_bytes().as_slice());
+        bytes.extend_from_slice(self.log_number.to_le_bytes().as_slice());
+        bytes.extend_from_slice(self.creation_time.to_le_bytes().as_slice());
+        bytes.extend_from_slice((self.comparator_name.len() as u16).to_le_bytes().as_slice());
+        bytes.extend_from_slice(self.comparator_name.as_bytes());
+        bytes.push(if self.recycled { RECYCLED_FLAG } else { 0 });
+
+        let mut hasher = Hasher::new();
+        hasher.update(bytes.as_slice());
+        bytes.extend_from_slice(hasher.finalize().to_le_bytes().as_slice());
+
+        bytes
+    }
+
+    /// Reads the header at the beginning of `file`. Returns `None` and rewinds the file when it
+    /// was written in the legacy format, fails on any format other than `LOG_FORMAT_VERSION`.
+    pub fn read(file: &mut File, file_name: &str) -> Result<Option<Self>> {
+        let read_error = || Error::UnableToReadLogFile(file_name.to_string());
+        let corrupted = || Error::CorruptedLogHeader(file_name.to_string());
+
+        let mut fixed = [0u8; FIXED_HEADER_SIZE];
+
+        match file.read_exact(&mut fixed) {
+            Ok(_) if fixed[..LOG_MAGIC.len()] == LOG_MAGIC => (),
+            Err(error) if error.kind() != ErrorKind::UnexpectedEof => return Err(read_error()),
+            _ => {
+                file.seek(SeekFrom::Start(0)).map_err(|_| read_error())?;
+                return Ok(None);
+            }
+        };
+
+        let (_, right) = fixed.split_at(LOG_MAGIC.len());
+        let (format_version, right) = right.split_at(size_of::<u32>());
+        let (log_number, right) = right.split_at(size_of::<u64>());
+        let (creation_time, name_size) = right.split_at(size_of::<u64>());
+        let format_version = u32::from_le_bytes(format_version.try_into().unwrap());
+        let name_size = u16::from_le_bytes(name_size.try_into().unwrap()) as usize;
+
+        if format_version != LOG_FORMAT_VERSION {
+            return Err(Error::UnsupportedLogFormat(
+                file_name.to_string(),
+                format_version,
+            ));
+        }
+
+        let mut rest = vec![0u8; name_size + FLAGS_SIZE + size_of::<u32>()];
+        file.read_exact(rest.as_mut_slice())
+            .map_err(|_| corrupted())?;
+        let (name, right) = rest.split_at(name_size);
+        let (flags, crc) = right.split_at(FLAGS_SIZE);
+
+        let mut hasher = Hasher::new();
+        hasher.update(&fixed);
+        hasher.update(name);
+        hasher.update(flags);
+
+        if hasher.finalize() != u32::from_le_bytes(crc.try_into().unwrap()) {
+            return Err(corrupted());
+        }
+
+        Ok(Some(LogFileHeader {
+            format_version,
+            log_number: u64::from_le_bytes(log_number.try_into().unwrap()),
+            creation_time: u64::from_le_bytes(creation_time.try_into().unwrap()),
+            comparator_name: String::from_utf8(name.to_vec()).map_err(|_| corrupted())?,
+            recycled: flags[0] & RECYCLED_FLAG != 0,
+        }))
+    }
+}
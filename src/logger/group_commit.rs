@@ -0,0 +1,138 @@
+use crate::error::{Error, Result};
+use bytes::Bytes;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Condvar, Mutex};
+
+/// A leader stops collecting records once its group reaches this size.
+pub const MAX_GROUP_SIZE: usize = 1024 * 1024;
+
+pub struct PendingRecord {
+    pub key: Bytes,
+    pub value: Bytes,
+    pub sync: bool,
+    // the next record belongs to the same write
+    pub continued: bool,
+}
+
+impl PendingRecord {
+    fn len(&self) -> usize {
+        self.key.len() + self.value.len()
+    }
+}
+
+struct QueueState {
+    pending: VecDeque<(u64, Vec<PendingRecord>)>,
+    finished: HashMap<u64, Result<()>>,
+    leading: bool,
+    next_ticket: u64,
+}
+
+/// Lets concurrent writers share a single write. The first writer that finds no active leader
+/// becomes the leader, writes every queued record as one group and wakes the followers with
+/// the result of the group.
+pub struct GroupCommitQueue {
+    state: Mutex<QueueState>,
+    condvar: Condvar,
+}
+
+#[allow(dead_code)]
+impl GroupCommitQueue {
+    pub fn new() -> Self {
+        GroupCommitQueue {
+            state: Mutex::new(QueueState {
+                pending: VecDeque::new(),
+                finished: HashMap::new(),
+                leading: false,
+                next_ticket: 0,
+            }),
+            condvar: Condvar::new(),
+        }
+    }
+
+    /// Blocks until the group containing `record` is written by `write_group`. The writers of a
+    /// failed group receive the error of its leader.
+    pub fn commit<W>(&self, record: PendingRecord, write_group: W) -> Result<()>
+    where
+        W: Fn(&[PendingRecord]) -> Result<()>,
+    {
+        self.commit_batch(|| Ok(vec![record]), write_group)
+    }
+
+    /// Commits like `commit` the records `build` returns, they are written next to each other in
+    /// the same group. `build` runs under the lock of the queue as the records take their place
+    /// in it, so the records are written in the order of the `build` calls. Nothing is queued
+    /// when it fails.
+    pub fn commit_batch<B, W>(&self, build: B, write_group: W) -> Result<()>
+    where
+        B: FnOnce() -> Result<Vec<PendingRecord>>,
+        W: Fn(&[PendingRecord]) -> Result<()>,
+    {
+        let mut state = self.state.lock().unwrap();
+        let records = build()?;
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        state.pending.push_back((ticket, records));
+
+        loop {
+            if let Some(result) = state.finished.remove(&ticket) {
+                break result;
+            }
+
+            if state.leading {
+                state = self.condvar.wait(state).unwrap();
+                continue;
+            }
+
+            state.leading = true;
+
+            let mut group_size = 0;
+            let mut tickets = vec![];
+            let mut group = vec![];
+
+            while let Some((_, next)) = state.pending.front() {
+                let next_size = next.iter().map(|record| record.len()).sum::<usize>();
+
+                if !group.is_empty() && group_size + next_size > MAX_GROUP_SIZE {
+                    break;
+                }
+
+                let (next_ticket, next) = state.pending.pop_front().unwrap();
+                group_size += next_size;
+                tickets.push(next_ticket);
+                group.extend(next);
+            }
+
+            drop(state);
+            let mut group_result = GroupResult {
+                queue: self,
+                tickets,
+                result: None,
+            };
+            group_result.result = Some(write_group(group.as_slice()));
+            drop(group_result);
+            state = self.state.lock().unwrap();
+        }
+    }
+}
+
+// hands the result of a group to its writers and steps down the leader, a leader panicking in
+// `write_group` fails the group instead of leaving its writers waiting forever
+struct GroupResult<'a> {
+    queue: &'a GroupCommitQueue,
+    tickets: Vec<u64>,
+    result: Option<Result<()>>,
+}
+
+impl Drop for GroupResult<'_> {
+    fn drop(&mut self) {
+        let result = self.result.take().unwrap_or(Err(Error::GroupCommitAborted));
+        let mut state = self.queue.state.lock().unwrap();
+
+        for ticket in self.tickets.drain(..) {
+            state.finished.insert(ticket, result.clone());
+        }
+
+        state.leading = false;
+        self.queue.condvar.notify_all();
+    }
+}
@@ -1,11 +1,18 @@
 use crate::error::{Error, Result};
-use crate::logger::chunk::{Chunk, MIN_CHUNK_SIZE};
-use crate::logger::record::Record;
+use crate::format::CompressionType;
+use crate::logger::chunk::{chunk_head_size, chunk_log_number, min_chunk_size, Chunk, ChunkType};
+use crate::logger::file_header::{LogFileHeader, LEGACY_FORMAT_VERSION};
+use crate::logger::record::{Record, CONTINUED_FLAG};
+use crate::logger::LogNumber;
+use crate::WalRecoveryMode;
+use std::convert::TryFrom;
 use std::fs::File;
-use std::io::Read;
+use std::io::{Read, Seek, SeekFrom};
+use std::mem::take;
 
 pub struct RecordWrapper {
     chunk_data: Vec<u8>,
+    continued: bool,
 }
 
 #[allow(dead_code)]
@@ -13,87 +20,416 @@ impl RecordWrapper {
     pub fn record(&self) -> Record {
         Record::from(self.chunk_data.as_slice())
     }
+
+    /// Whether the next record belongs to the same write, never set in the legacy format.
+    pub fn continued(&self) -> bool {
+        self.continued
+    }
+}
+
+/// What a recovery had to give up to read a log.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RecoveryReport {
+    /// The bytes skipped over, a corrupted chunk drops the rest of its block so the number of
+    /// records they held is unknown.
+    pub dropped_bytes: usize,
+    /// Set when `WalRecoveryMode::PointInTimeRecovery` stopped before the end of the log, the
+    /// logs after it must not be replayed.
+    pub stopped_early: bool,
+    /// The logs not replayed because the recovery stopped early, they are moved to the `lost`
+    /// directory of the database rather than deleted.
+    pub lost_logs: Vec<LogNumber>,
+    /// Set when the database was last closed with writes missing from the logs, because they
+    /// were written with `WriteOptions::disable_wal` or were not flushed with the manual flush.
+    pub unlogged_writes_lost: bool,
+}
+
+impl RecoveryReport {
+    pub fn merge(&mut self, other: &RecoveryReport) {
+        self.dropped_bytes += other.dropped_bytes;
+        self.stopped_early |= other.stopped_early;
+        self.lost_logs.extend(other.lost_logs.iter());
+        self.unlogged_writes_lost |= other.unlogged_writes_lost;
+    }
+}
+
+/// Where a record ends in a log file, the reading can be resumed from it.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct LogPosition {
+    block_offset: u64,
+    offset: usize,
+}
+
+enum ChunkRead {
+    Chunk(ChunkType, Vec<u8>),
+    // the framing cannot be trusted anymore, the rest of the block is dropped
+    Corrupted {
+        size: usize,
+        tail: bool,
+        error: Error,
+    },
+    EndOfFile {
+        truncated: usize,
+    },
 }
 
 pub struct LogIterator {
     file: File,
     file_name: String,
     block_size: usize,
-    suffix: Vec<u8>,
+    recovery_mode: WalRecoveryMode,
+    format_version: u32,
+    log_number: LogNumber,
+    recycled: bool,
+    block: Vec<u8>,
+    block_offset: u64,
+    offset: usize,
+    record_end: Option<LogPosition>,
     end_of_file: bool,
+    finished: bool,
+    report: RecoveryReport,
 }
 
+#[allow(dead_code)]
 impl LogIterator {
-    pub fn new(file_name: String, block_size: usize, file: File) -> Self {
+    pub fn new(
+        file_name: String,
+        block_size: usize,
+        file: File,
+        recovery_mode: WalRecoveryMode,
+        // `None` for the legacy files without a header
+        header: Option<&LogFileHeader>,
+    ) -> Self {
+        let format_version = header.map_or(LEGACY_FORMAT_VERSION, |header| header.format_version);
+
         LogIterator {
             file,
             file_name,
             block_size,
-            suffix: vec![],
+            recovery_mode,
+            format_version,
+            // the legacy chunks are not salted, their log number is never used
+            log_number: header.map_or(0, |header| header.log_number),
+            recycled: header.is_some_and(|header| header.recycled),
+            block: vec![],
+            block_offset: 0,
+            offset: 0,
+            record_end: None,
             end_of_file: false,
+            finished: false,
+            report: RecoveryReport::default(),
         }
     }
-}
 
-impl Iterator for LogIterator {
-    type Item = Result<RecordWrapper>;
+    pub fn report(&self) -> &RecoveryReport {
+        &self.report
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let mut data = vec![];
+    /// Whether the log was written in the legacy format, whose records hold their numbers in
+    /// native byte order.
+    pub fn is_legacy(&self) -> bool {
+        self.format_version == LEGACY_FORMAT_VERSION
+    }
 
-        if self.end_of_file && self.suffix.len() < MIN_CHUNK_SIZE {
-            return None;
+    /// The end of the last record returned, `None` before the first one.
+    pub fn last_record_end(&self) -> Option<LogPosition> {
+        self.record_end
+    }
+
+    /// Continues the reading after the record ending at `position`. The block is read again, so
+    /// the chunks appended to it since the position was taken are found.
+    pub fn seek(&mut self, position: LogPosition) -> Result<()> {
+        self.file
+            .seek(SeekFrom::Start(position.block_offset))
+            .map_err(|_| Error::UnableToReadLogFile(self.file_name.clone()))?;
+        self.read_block()?;
+        self.offset = position.offset;
+        self.record_end = Some(position);
+
+        Ok(())
+    }
+
+    fn read_block(&mut self) -> Result<()> {
+        self.block_offset = self
+            .file
+            .stream_position()
+            .map_err(|_| Error::UnableToReadLogFile(self.file_name.clone()))?;
+
+        let mut block = vec![0; self.block_size];
+        let mut size = 0;
+
+        while size < self.block_size {
+            match self.file.read(&mut block[size..]) {
+                Ok(0) => break,
+                Ok(bytes) => size += bytes,
+                Err(_) => return Err(Error::UnableToReadLogFile(self.file_name.clone())),
+            }
         }
 
+        block.truncate(size);
+        self.end_of_file = size < self.block_size;
+        self.block = block;
+        self.offset = 0;
+
+        Ok(())
+    }
+
+    fn read_chunk(&mut self) -> Result<ChunkRead> {
+        let head_size = chunk_head_size(self.format_version);
+
         loop {
-            let mut block = vec![0; self.block_size];
-            let mut block_ref = if self.suffix.len() >= MIN_CHUNK_SIZE {
-                self.suffix.as_slice()
-            } else {
-                let bytes = match self.file.read(&mut block[..]) {
-                    Ok(bytes) => bytes,
-                    Err(_) => return Some(Err(Error::UnableToReadLogFile(self.file_name.clone()))),
-                };
+            let left = self.block.len() - self.offset;
+
+            // the writer pads the rest of a block too short for a chunk, a chunk shorter than
+            // that is still read at the end of the file
+            if left == 0 || self.block_size - self.offset < min_chunk_size(self.format_version) {
+                if self.end_of_file {
+                    self.offset = self.block.len();
 
-                if bytes != self.block_size {
-                    self.end_of_file = true
+                    return Ok(ChunkRead::EndOfFile { truncated: left });
                 }
 
-                block.as_slice().split_at(bytes).0
-            };
+                self.read_block()?;
+                continue;
+            }
 
-            let find_ending_chunk = loop {
-                let chunk: Chunk = block_ref.into();
+            // a head torn by the end of the file
+            if left < head_size {
+                self.offset = self.block.len();
 
-                if !chunk.check_crc32() {
-                    return Some(Err(Error::UnexpectedChunkCRC(self.file_name.clone())));
-                }
+                return Ok(ChunkRead::EndOfFile { truncated: left });
+            }
 
-                let ty = chunk.ty();
-                let size = chunk.len();
-                data.extend_from_slice(chunk.data.first().cloned().unwrap());
+            let bytes = &self.block[self.offset..];
+            let size_bytes = [bytes[4], bytes[5]];
+            let data_size = if self.format_version == LEGACY_FORMAT_VERSION {
+                u16::from_ne_bytes(size_bytes)
+            } else {
+                u16::from_le_bytes(size_bytes)
+            } as usize;
+
+            // the remains of the previous log of a reused file, the log ends before them
+            if self.recycled
+                && chunk_log_number(bytes).is_some_and(|number| number != self.log_number as u32)
+            {
+                self.offset = self.block.len();
+
+                return Ok(ChunkRead::EndOfFile { truncated: 0 });
+            }
+
+            // a torn write at the end of the file
+            if head_size + data_size > left && self.end_of_file {
+                self.offset = self.block.len();
 
-                let left = block_ref.len() - size;
-                block_ref = block_ref.split_at(size).1;
+                return Ok(ChunkRead::EndOfFile { truncated: left });
+            }
 
-                if left < MIN_CHUNK_SIZE {
-                    break ty.is_ending();
-                } else if ty.is_ending() {
-                    break true;
+            let result = match Chunk::decode(bytes, self.format_version) {
+                Ok(chunk) if chunk.check_crc32(self.log_number) => {
+                    Ok((chunk.ty(), chunk.data[0].to_vec()))
                 }
+                Ok(_) => Err(Error::UnexpectedChunkCRC(self.file_name.clone())),
+                Err(_) => Err(Error::CorruptedLogRecord(self.file_name.clone())),
             };
 
-            if block_ref.len() >= MIN_CHUNK_SIZE {
-                self.suffix = Vec::from(block_ref);
-            } else {
-                self.suffix = vec![];
+            return Ok(match result {
+                Ok((ty, data)) => {
+                    self.offset += head_size + data.len();
+                    ChunkRead::Chunk(ty, data)
+                }
+                Err(error) => {
+                    self.offset = self.block.len();
+                    ChunkRead::Corrupted {
+                        size: left,
+                        tail: self.end_of_file,
+                        error,
+                    }
+                }
+            });
+        }
+    }
+
+    // returns the result of `next` if the iteration cannot continue
+    fn corruption(
+        &mut self,
+        dropped_bytes: usize,
+        tail: bool,
+        error: Error,
+    ) -> Option<Option<Result<RecordWrapper>>> {
+        self.report.dropped_bytes += dropped_bytes;
+
+        match self.recovery_mode {
+            WalRecoveryMode::SkipAnyCorruptedRecords => None,
+            WalRecoveryMode::TolerateCorruptedTailRecords if tail => {
+                self.finished = true;
+                Some(None)
+            }
+            WalRecoveryMode::PointInTimeRecovery => {
+                self.finished = true;
+                self.report.stopped_early = true;
+                Some(None)
             }
+            _ => {
+                self.finished = true;
+                Some(Some(Err(error)))
+            }
+        }
+    }
+
+    // checks the framing of the record, strips the leading byte the legacy format lacks and
+    // decompresses the value
+    fn decode_record(&self, mut data: Vec<u8>) -> Result<RecordWrapper> {
+        let corrupted = || Error::CorruptedLogRecord(self.file_name.clone());
+
+        let leading_byte = if self.format_version == LEGACY_FORMAT_VERSION {
+            CompressionType::NoCompression as u8
+        } else if data.is_empty() {
+            return Err(corrupted());
+        } else {
+            data.remove(0)
+        };
+        let continued = leading_byte & CONTINUED_FLAG != 0;
+        let compression =
+            CompressionType::try_from(leading_byte & !CONTINUED_FLAG).map_err(|_| corrupted())?;
 
-            if find_ending_chunk {
-                break;
+        // a record that passed the checksums can still be malformed if the checksum collided
+        if !Record::is_valid(data.as_slice()) {
+            return Err(corrupted());
+        }
+
+        if compression == CompressionType::NoCompression {
+            return Ok(RecordWrapper {
+                chunk_data: data,
+                continued,
+            });
+        }
+
+        let record = Record::from(data.as_slice());
+        let value = compression
+            .decompress(record.value())
+            .map_err(|_| corrupted())?;
+
+        Ok(RecordWrapper {
+            chunk_data: Record::new(record.key(), value.as_slice()).encode(),
+            continued,
+        })
+    }
+
+    // returns the result of `next` unless the record is dropped
+    fn complete(&mut self, data: Vec<u8>) -> Option<Option<Result<RecordWrapper>>> {
+        let size = data.len();
+
+        match self.decode_record(data) {
+            Ok(wrapper) => {
+                self.record_end = Some(LogPosition {
+                    block_offset: self.block_offset,
+                    offset: self.offset,
+                });
+
+                Some(Some(Ok(wrapper)))
+            }
+            Err(error) => self.corruption(size, false, error),
+        }
+    }
+
+    fn incomplete_tail(&mut self, dropped_bytes: usize) -> Option<Result<RecordWrapper>> {
+        self.report.dropped_bytes += dropped_bytes;
+
+        match self.recovery_mode {
+            WalRecoveryMode::AbsoluteConsistency => {
+                Some(Err(Error::IncompleteLogRecord(self.file_name.clone())))
+            }
+            WalRecoveryMode::PointInTimeRecovery => {
+                self.report.stopped_early = true;
+                None
             }
+            _ => None,
+        }
+    }
+}
+
+impl Iterator for LogIterator {
+    type Item = Result<RecordWrapper>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
         }
 
-        Some(Ok(RecordWrapper { chunk_data: data }))
+        let mut data: Vec<u8> = vec![];
+        let mut fragmented = false;
+
+        loop {
+            let chunk_read = match self.read_chunk() {
+                Ok(chunk_read) => chunk_read,
+                Err(error) => {
+                    self.finished = true;
+                    return Some(Err(error));
+                }
+            };
+
+            match chunk_read {
+                ChunkRead::Chunk(ty, chunk_data) => match ty {
+                    ChunkType::Full | ChunkType::First => {
+                        // the previous record never ended
+                        if fragmented {
+                            let error = Error::CorruptedLogRecord(self.file_name.clone());
+
+                            if let Some(result) = self.corruption(data.len(), false, error) {
+                                return result;
+                            }
+                        }
+
+                        data = chunk_data;
+                        fragmented = ty == ChunkType::First;
+
+                        if !fragmented {
+                            if let Some(result) = self.complete(take(&mut data)) {
+                                return result;
+                            }
+                        }
+                    }
+                    ChunkType::Middle | ChunkType::Last => {
+                        // the record never started
+                        if !fragmented {
+                            let error = Error::CorruptedLogRecord(self.file_name.clone());
+
+                            if let Some(result) = self.corruption(chunk_data.len(), false, error) {
+                                return result;
+                            }
+
+                            continue;
+                        }
+
+                        data.extend_from_slice(chunk_data.as_slice());
+
+                        if ty == ChunkType::Last {
+                            fragmented = false;
+
+                            if let Some(result) = self.complete(take(&mut data)) {
+                                return result;
+                            }
+                        }
+                    }
+                },
+                ChunkRead::Corrupted { size, tail, error } => {
+                    if let Some(result) = self.corruption(size + data.len(), tail, error) {
+                        return result;
+                    }
+
+                    data.clear();
+                    fragmented = false;
+                }
+                ChunkRead::EndOfFile { truncated } => {
+                    self.finished = true;
+
+                    return if fragmented || truncated > 0 {
+                        self.incomplete_tail(data.len() + truncated)
+                    } else {
+                        None
+                    };
+                }
+            }
+        }
     }
 }
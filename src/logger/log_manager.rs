@@ -1,42 +1,439 @@
 use crate::error::{Error, Result};
-use crate::logger::log_iterator::LogIterator;
+use crate::format::CompressionType;
+use crate::logger::file_header::LogFileHeader;
+use crate::logger::group_commit::{GroupCommitQueue, PendingRecord};
+use crate::logger::log_iterator::{LogIterator, LogPosition};
 use crate::logger::record::{Record, RecordChunk};
+use crate::{IOPriority, RateLimiter, WalRecoveryMode, WalSyncMode};
+use bytes::Bytes;
 use regex::Regex;
 use std::cmp::max;
-use std::fs::{read_dir, remove_file, DirEntry, File};
-use std::io::{Error as IOError, IoSlice, Write};
+use std::collections::{HashMap, VecDeque};
+use std::fs::{create_dir_all, read_dir, remove_file, rename, DirEntry, File, OpenOptions};
+use std::io::{Error as IOError, IoSlice, Result as IOResult, Write};
+use std::mem::take;
 use std::path::{Path, PathBuf};
 use std::result::Result as STDResult;
-use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::thread::{sleep, spawn};
+use std::time::{Duration, SystemTime};
 
 pub type LogNumber = u64;
 
+const ARCHIVE_DIR: &str = "archive";
+// the logs a recovery stopped before, kept for a manual recovery
+const LOST_DIR: &str = "lost";
+// exists while writes that are not in the logs may be lost
+const UNLOGGED_MARKER: &str = "UNLOGGED";
+
 pub struct LogManager {
     dir: PathBuf,
+    comparator_name: String,
     current_log_number: AtomicU64,
     current_file: Mutex<File>,
     remaining_size: AtomicUsize,
     block_size: AtomicUsize,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    sync_mode: WalSyncMode,
+    recovery_mode: WalRecoveryMode,
+    group_commit: GroupCommitQueue,
+    recycle_log_file_num: usize,
+    recycled_logs: Mutex<VecDeque<LogNumber>>,
+    preallocate_size: Option<usize>,
+    compression: CompressionType,
+    archive_ttl: Option<Duration>,
+    archive_size_limit: Option<u64>,
+    manual_flush: bool,
+    buffer: Mutex<Vec<PendingRecord>>,
+    // the unlogged marker was created by this manager
+    unlogged: AtomicBool,
+    // writes skipped the log, the marker is kept when the manager is dropped
+    skipped_records: AtomicBool,
+    unlogged_marker_found: bool,
 }
 
 // todo: use
 #[allow(dead_code)]
 impl LogManager {
-    pub fn new(dir: PathBuf, first_log_number: LogNumber, block_size: usize) -> Result<Self> {
+    pub fn new(
+        dir: PathBuf,
+        first_log_number: LogNumber,
+        block_size: usize,
+        comparator_name: &str,
+    ) -> Result<Self> {
+        let mut recycled_logs = Self::exist_file_numbers(dir.as_path(), r"^recycle_(\d+)$")?;
+        recycled_logs.sort_unstable();
+
+        let unlogged_marker = dir.join(UNLOGGED_MARKER);
+        let unlogged_marker_found = unlogged_marker.exists();
+
+        if unlogged_marker_found {
+            remove_file(unlogged_marker.as_path()).map_err(|_| {
+                Error::UnableToTruncateLogFile(unlogged_marker.to_str().unwrap().to_string())
+            })?;
+        }
+
         Ok(LogManager {
-            dir: dir.clone(),
             current_log_number: AtomicU64::new(first_log_number),
-            current_file: Mutex::new(
-                File::create(dir.join(format!("log_{}", first_log_number))).map_err(|_| {
-                    Error::UnableToCreateFile(dir.as_os_str().to_str().unwrap().to_string())
-                })?,
-            ),
+            current_file: Mutex::new(Self::create_log_file(
+                dir.as_path(),
+                first_log_number,
+                comparator_name,
+            )?),
+            dir,
+            comparator_name: comparator_name.to_string(),
             remaining_size: AtomicUsize::new(block_size),
             block_size: AtomicUsize::new(block_size),
+            rate_limiter: None,
+            sync_mode: WalSyncMode::NoSync,
+            recovery_mode: WalRecoveryMode::TolerateCorruptedTailRecords,
+            group_commit: GroupCommitQueue::new(),
+            recycle_log_file_num: 0,
+            recycled_logs: Mutex::new(recycled_logs.into_iter().collect()),
+            preallocate_size: None,
+            compression: CompressionType::NoCompression,
+            archive_ttl: None,
+            archive_size_limit: None,
+            manual_flush: false,
+            buffer: Mutex::new(vec![]),
+            unlogged: AtomicBool::new(false),
+            skipped_records: AtomicBool::new(false),
+            unlogged_marker_found,
         })
     }
 
+    pub fn set_sync_mode(&mut self, sync_mode: WalSyncMode) {
+        self.sync_mode = sync_mode;
+    }
+
+    pub fn set_recovery_mode(&mut self, recovery_mode: WalRecoveryMode) {
+        self.recovery_mode = recovery_mode;
+    }
+
+    /// Starts the background syncing of `WalSyncMode::Periodic`, the thread stops once the
+    /// manager is dropped.
+    pub fn start_periodic_sync(manager: &Arc<LogManager>) {
+        if let WalSyncMode::Periodic(interval) = manager.sync_mode {
+            let weak: Weak<LogManager> = Arc::downgrade(manager);
+
+            spawn(move || loop {
+                sleep(interval);
+
+                match weak.upgrade() {
+                    // a failed sync is retried on the next tick
+                    Some(manager) => manager.sync().ok(),
+                    None => break,
+                };
+            });
+        }
+    }
+
+    /// Forces the current log file to disk.
+    pub fn sync(&self) -> Result<()> {
+        let file = self
+            .lock_current_file()
+            .try_clone()
+            .map_err(|_| self.write_error())?;
+
+        file.sync_data().map_err(|_| self.write_error())
+    }
+
+    fn lock_current_file(&self) -> MutexGuard<'_, File> {
+        match self.current_file.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    fn write_error(&self) -> Error {
+        Error::UnableToWriteLogFile(self.current_log_file().to_str().unwrap().to_string())
+    }
+
+    fn sync_dir(&self) -> Result<()> {
+        File::open(self.dir())
+            .and_then(|dir| dir.sync_all())
+            .map_err(|_| Error::UnableToSyncDir(self.dir().to_str().unwrap().to_string()))
+    }
+
+    pub fn set_rate_limiter(&mut self, rate_limiter: Option<Arc<RateLimiter>>) {
+        self.rate_limiter = rate_limiter;
+    }
+
+    /// Keeps up to `recycle_log_file_num` obsolete log files to be reused by the next logs
+    /// instead of deleting them.
+    pub fn set_recycle_log_file_num(&mut self, recycle_log_file_num: usize) {
+        self.recycle_log_file_num = recycle_log_file_num;
+    }
+
+    /// Reserves `preallocate_size` bytes of disk space for every new log file, the current one
+    /// included.
+    pub fn set_preallocate_size(&mut self, preallocate_size: Option<usize>) {
+        self.preallocate_size = preallocate_size;
+
+        if let Some(size) = preallocate_size {
+            // only an optimization, the log grows as usual when it fails
+            Self::preallocate(&self.lock_current_file(), size).ok();
+        }
+    }
+
+    pub fn set_compression(&mut self, compression: CompressionType) {
+        self.compression = compression;
+    }
+
+    /// Moves the obsolete logs into the archive directory instead of deleting them when either
+    /// limit is set. The archived logs older than `ttl` are deleted, then the oldest ones until
+    /// the archive fits in `size_limit` bytes. Archiving takes precedence over recycling.
+    pub fn set_archive(&mut self, ttl: Option<Duration>, size_limit: Option<u64>) {
+        self.archive_ttl = ttl;
+        self.archive_size_limit = size_limit;
+    }
+
+    /// Buffers the records in memory until `flush` is called instead of writing them at once.
+    pub fn set_manual_flush(&mut self, manual_flush: bool) {
+        self.manual_flush = manual_flush;
+    }
+
+    /// Whether the last manager of the directory was dropped with writes missing from the logs,
+    /// because they skipped the log or were never flushed.
+    pub fn unlogged_marker_found(&self) -> bool {
+        self.unlogged_marker_found
+    }
+
+    fn mark_unlogged(&self) -> Result<()> {
+        if !self.unlogged.swap(true, Ordering::SeqCst) {
+            let marker = self.dir().join(UNLOGGED_MARKER);
+
+            File::create(marker.as_path())
+                .map_err(|_| Error::UnableToCreateFile(marker.to_str().unwrap().to_string()))?;
+            self.sync_dir()?;
+        }
+
+        Ok(())
+    }
+
+    /// Notes a write that is not logged, it will be missing after a restart.
+    pub fn skip_record(&self) -> Result<()> {
+        self.skipped_records.store(true, Ordering::SeqCst);
+        self.mark_unlogged()
+    }
+
+    fn lock_buffer(&self) -> MutexGuard<'_, Vec<PendingRecord>> {
+        match self.buffer.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Writes the buffered records of the manual flush, and syncs the log if `sync` is set.
+    pub fn flush(&self, sync: bool) -> Result<()> {
+        let mut buffer = self.lock_buffer();
+
+        self.flush_buffer(&mut buffer)?;
+
+        if sync {
+            self.sync()?;
+        }
+
+        Ok(())
+    }
+
+    fn flush_buffer(&self, buffer: &mut Vec<PendingRecord>) -> Result<()> {
+        if buffer.is_empty() {
+            return Ok(());
+        }
+
+        let group = take(buffer);
+        let result = self.write_group(group.as_slice());
+
+        if result.is_err() {
+            // kept for the next flush
+            *buffer = group;
+        }
+
+        result
+    }
+
+    fn archive_enabled(&self) -> bool {
+        self.archive_ttl.is_some() || self.archive_size_limit.is_some()
+    }
+
+    fn archive_dir(dir: &Path) -> PathBuf {
+        dir.join(ARCHIVE_DIR)
+    }
+
+    fn archived_log_file(&self, log_number: LogNumber) -> PathBuf {
+        Self::archive_dir(self.dir()).join(format!("log_{}", log_number))
+    }
+
+    /// Numbers of the logs in the archive directory of `dir`, in ascending order.
+    pub fn archived_log_numbers(dir: &Path) -> Result<Vec<LogNumber>> {
+        Self::sorted_log_numbers(Self::archive_dir(dir).as_path())
+    }
+
+    /// Numbers of the logs set aside by `set_aside_log` in `dir`, in ascending order.
+    pub fn lost_log_numbers(dir: &Path) -> Result<Vec<LogNumber>> {
+        Self::sorted_log_numbers(dir.join(LOST_DIR).as_path())
+    }
+
+    fn sorted_log_numbers(dir: &Path) -> Result<Vec<LogNumber>> {
+        if !dir.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut log_numbers = Self::exist_log_numbers(dir)?;
+        log_numbers.sort_unstable();
+
+        Ok(log_numbers)
+    }
+
+    /// Moves a log that must not be replayed into the `lost` directory, where it is neither
+    /// replayed nor collected.
+    pub fn set_aside_log(&self, log_number: LogNumber) -> Result<()> {
+        let file_path = self.log_file(log_number);
+        let lost_dir = self.dir().join(LOST_DIR);
+
+        create_dir_all(lost_dir.as_path())
+            .map_err(|_| Error::UnableToCreateFile(lost_dir.to_str().unwrap().to_string()))?;
+        rename(
+            file_path.as_path(),
+            lost_dir.join(format!("log_{}", log_number)),
+        )
+        .map_err(|_| Error::UnableToTruncateLogFile(file_path.to_str().unwrap().to_string()))?;
+
+        if self.sync_mode != WalSyncMode::NoSync {
+            self.sync_dir()?;
+        }
+
+        Ok(())
+    }
+
+    /// Deletes the archived logs beyond the ttl or the size limit, except the `pinned_logs` a
+    /// reader is still going through.
+    pub fn purge_archive(&self, pinned_logs: &HashMap<LogNumber, usize>) -> Result<()> {
+        if !self.archive_enabled() {
+            return Ok(());
+        }
+
+        let mut archived_logs = vec![];
+
+        for log_number in Self::archived_log_numbers(self.dir())? {
+            let file_path = self.archived_log_file(log_number);
+            let path_str = file_path.to_str().unwrap().to_string();
+            let metadata = file_path
+                .metadata()
+                .map_err(|_| Error::UnableToReadLogFile(path_str))?;
+            let age = metadata
+                .modified()
+                .ok()
+                .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+                .unwrap_or_default();
+
+            archived_logs.push((log_number, metadata.len(), age));
+        }
+
+        archived_logs.sort_unstable();
+
+        let mut total_size: u64 = archived_logs.iter().map(|(_, size, _)| size).sum();
+
+        for (log_number, size, age) in archived_logs {
+            let expired = self.archive_ttl.is_some_and(|ttl| age >= ttl);
+            let oversized = self
+                .archive_size_limit
+                .is_some_and(|limit| total_size > limit);
+
+            if (expired || oversized) && !pinned_logs.contains_key(&log_number) {
+                let file_path = self.archived_log_file(log_number);
+                let path_str = file_path.to_str().unwrap().to_string();
+
+                remove_file(file_path).map_err(|_| Error::UnableToTruncateLogFile(path_str))?;
+                total_size -= size;
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn preallocate(file: &File, size: usize) -> IOResult<()> {
+        use std::os::unix::io::AsRawFd;
+
+        // the size of the file is kept, so the reserved space is never read as chunks
+        let result = unsafe {
+            libc::fallocate(
+                file.as_raw_fd(),
+                libc::FALLOC_FL_KEEP_SIZE,
+                0,
+                size as libc::off_t,
+            )
+        };
+
+        match result {
+            0 => Ok(()),
+            _ => Err(IOError::last_os_error()),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn preallocate(_file: &File, _size: usize) -> IOResult<()> {
+        Ok(())
+    }
+
+    fn lock_recycled_logs(&self) -> MutexGuard<'_, VecDeque<LogNumber>> {
+        match self.recycled_logs.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Number of the obsolete log files kept to be reused.
+    pub fn recycled_log_numbers(&self) -> Vec<LogNumber> {
+        self.lock_recycled_logs().iter().copied().collect()
+    }
+
+    fn recycled_log_file(&self, log_number: LogNumber) -> PathBuf {
+        self.dir().join(format!("recycle_{}", log_number))
+    }
+
+    fn reuse_log_file(&self, recycled: LogNumber, log_number: LogNumber) -> Result<File> {
+        let create_error =
+            || Error::UnableToCreateFile(self.dir().as_os_str().to_str().unwrap().to_string());
+
+        rename(self.recycled_log_file(recycled), self.log_file(log_number))
+            .map_err(|_| create_error())?;
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .open(self.log_file(log_number))
+            .map_err(|_| create_error())?;
+
+        let header = LogFileHeader {
+            recycled: true,
+            ..LogFileHeader::new(log_number, self.comparator_name.as_str())
+        };
+
+        file.write_all(header.encode().as_slice())
+            .map_err(|_| create_error())?;
+
+        Ok(file)
+    }
+
+    fn create_log_file(dir: &Path, log_number: LogNumber, comparator_name: &str) -> Result<File> {
+        let create_error =
+            || Error::UnableToCreateFile(dir.as_os_str().to_str().unwrap().to_string());
+        let mut file =
+            File::create(dir.join(format!("log_{}", log_number))).map_err(|_| create_error())?;
+
+        file.write_all(
+            LogFileHeader::new(log_number, comparator_name)
+                .encode()
+                .as_slice(),
+        )
+        .map_err(|_| create_error())?;
+
+        Ok(file)
+    }
+
     fn dir(&self) -> &Path {
         self.dir.as_path()
     }
@@ -45,20 +442,49 @@ impl LogManager {
         self.dir().join(format!("log_{}", log_number))
     }
 
+    pub fn current_log_number(&self) -> LogNumber {
+        self.current_log_number.load(Ordering::SeqCst)
+    }
+
     fn current_log_file(&self) -> PathBuf {
         self.log_file(self.current_log_number.load(Ordering::SeqCst))
     }
 
     pub fn freeze_current_file(&self, new_log_number: LogNumber) -> Result<()> {
-        let mut old_guard = match self.current_file.lock() {
-            Ok(guard) => guard,
-            Err(poisoned) => poisoned.into_inner(),
+        // the buffered records belong to the old log
+        let mut buffer = self.lock_buffer();
+        self.flush_buffer(&mut buffer)?;
+
+        let mut old_guard = self.lock_current_file();
+
+        if self.sync_mode != WalSyncMode::NoSync {
+            old_guard.sync_data().map_err(|_| self.write_error())?;
+        }
+
+        let recycled = self.lock_recycled_logs().pop_front();
+
+        *old_guard = match recycled {
+            Some(recycled) => self.reuse_log_file(recycled, new_log_number)?,
+            None => {
+                let file =
+                    Self::create_log_file(self.dir(), new_log_number, &self.comparator_name)?;
+
+                if let Some(size) = self.preallocate_size {
+                    Self::preallocate(&file, size).ok();
+                }
+
+                file
+            }
         };
-        *old_guard = File::create(self.log_file(new_log_number)).map_err(|_| {
-            Error::UnableToCreateFile(self.dir().as_os_str().to_str().unwrap().to_string())
-        })?;
         self.current_log_number
             .store(new_log_number, Ordering::SeqCst);
+        self.remaining_size
+            .store(self.block_size.load(Ordering::SeqCst), Ordering::SeqCst);
+        drop(old_guard);
+
+        if self.sync_mode != WalSyncMode::NoSync {
+            self.sync_dir()?;
+        }
 
         Ok(())
     }
@@ -66,49 +492,172 @@ impl LogManager {
     pub fn truncate_log(&self, log_number: LogNumber) -> Result<()> {
         let file_path = self.log_file(log_number);
         let path_str = file_path.to_str().unwrap().to_string();
+        if self.archive_enabled() {
+            let archive_dir = Self::archive_dir(self.dir());
+            create_dir_all(archive_dir.as_path()).map_err(|_| {
+                Error::UnableToCreateFile(archive_dir.to_str().unwrap().to_string())
+            })?;
+            rename(file_path, self.archived_log_file(log_number))
+                .map_err(|_| Error::UnableToTruncateLogFile(path_str))?;
+
+            return Ok(());
+        }
+
+        let mut recycled_logs = self.lock_recycled_logs();
+
+        if recycled_logs.len() < self.recycle_log_file_num {
+            rename(file_path, self.recycled_log_file(log_number))
+                .map_err(|_| Error::UnableToTruncateLogFile(path_str))?;
+            recycled_logs.push_back(log_number);
+
+            return Ok(());
+        }
 
         remove_file(file_path).map_err(|_| Error::UnableToTruncateLogFile(path_str))?;
 
         Ok(())
     }
 
-    pub fn insert_record(&self, record: Record) -> Result<()> {
-        let mut buffer = match self.current_file.lock() {
-            Ok(guard) => guard,
-            Err(poisoned) => poisoned.into_inner(),
+    /// The record is durable once this returns if `sync` is set or the manager syncs every write.
+    /// Concurrent callers are grouped into a single write and a single sync.
+    ///
+    /// With the manual flush, the record is buffered until the next `flush` unless `sync` is set,
+    /// in which case the whole buffer is written and synced.
+    pub fn insert_record(&self, record: Record, sync: bool) -> Result<()> {
+        self.insert_records(&[record], sync)
+    }
+
+    /// Writes the records next to each other, in the same group commit.
+    pub fn insert_records(&self, records: &[Record], sync: bool) -> Result<()> {
+        self.insert_records_with(sync, || {
+            Ok(records
+                .iter()
+                .map(|record| {
+                    (
+                        Bytes::copy_from_slice(record.key()),
+                        Bytes::copy_from_slice(record.value()),
+                    )
+                })
+                .collect())
+        })
+    }
+
+    /// Writes the keys and values `build` returns like `insert_records`. `build` runs as the
+    /// records take their place in the log, so the records are logged in the order of the
+    /// `build` calls. Nothing is logged when it fails.
+    pub fn insert_records_with<B>(&self, sync: bool, build: B) -> Result<()>
+    where
+        B: FnOnce() -> Result<Vec<(Bytes, Bytes)>>,
+    {
+        let pending = |records: Vec<(Bytes, Bytes)>| {
+            let count = records.len();
+
+            records
+                .into_iter()
+                .enumerate()
+                .map(|(index, (key, value))| PendingRecord {
+                    key,
+                    value,
+                    sync,
+                    continued: index + 1 < count,
+                })
+                .collect::<Vec<_>>()
         };
 
-        let (chunks, remaining_size) = record.get_chunks(
-            self.remaining_size.load(Ordering::SeqCst),
-            self.block_size.load(Ordering::SeqCst),
-        );
+        if self.manual_flush {
+            self.mark_unlogged()?;
+
+            let mut buffer = self.lock_buffer();
+            buffer.extend(pending(build()?));
+
+            return if sync {
+                self.flush_buffer(&mut buffer)
+            } else {
+                Ok(())
+            };
+        }
+
+        self.group_commit
+            .commit_batch(|| build().map(pending), |group| self.write_group(group))
+    }
+
+    fn write_group(&self, group: &[PendingRecord]) -> Result<()> {
+        let compressed_values: Vec<Option<Vec<u8>>> = group
+            .iter()
+            .map(|pending| {
+                self.compression
+                    .compress(pending.value.as_ref())
+                    .filter(|compressed| compressed.len() < pending.value.len())
+            })
+            .collect();
+
+        let records: Vec<Record> = group
+            .iter()
+            .zip(compressed_values.iter())
+            .map(|(pending, compressed)| match compressed {
+                Some(value) => Record::with_compression(
+                    pending.key.as_ref(),
+                    value.as_slice(),
+                    self.compression,
+                    pending.continued,
+                ),
+                None => Record::with_compression(
+                    pending.key.as_ref(),
+                    pending.value.as_ref(),
+                    CompressionType::NoCompression,
+                    pending.continued,
+                ),
+            })
+            .collect();
+
+        if let Some(rate_limiter) = self.rate_limiter.as_ref() {
+            rate_limiter.request(
+                records.iter().fold(0, |carry, record| carry + record.len()),
+                IOPriority::Wal,
+            );
+        }
+
+        let mut buffer = self.lock_current_file();
+        // read under the file lock, a rotation cannot happen in between
+        let log_number = self.current_log_number.load(Ordering::SeqCst);
+        let block_size = self.block_size.load(Ordering::SeqCst);
+        let mut remaining_size = self.remaining_size.load(Ordering::SeqCst);
+
+        let record_chunks: Vec<Vec<RecordChunk>> = records
+            .iter()
+            .map(|record| {
+                let (chunks, remaining) = record.get_chunks(remaining_size, block_size, log_number);
+                remaining_size = remaining;
+
+                chunks
+            })
+            .collect();
 
         self.remaining_size.store(remaining_size, Ordering::SeqCst);
 
         let slop: Vec<u8> = vec![
             0;
-            chunks.iter().fold(0, |carry, record_chunk| {
-                max(
-                    carry,
-                    match record_chunk {
-                        RecordChunk::Slop(size) => *size,
-                        _ => 0,
-                    },
-                )
-            })
+            record_chunks
+                .iter()
+                .flatten()
+                .fold(0, |carry, record_chunk| {
+                    max(
+                        carry,
+                        match record_chunk {
+                            RecordChunk::Slop(size) => *size,
+                            _ => 0,
+                        },
+                    )
+                })
         ];
 
         let mut slices = vec![];
 
-        for chunk in chunks.iter() {
+        for chunk in record_chunks.iter().flatten() {
             match chunk {
                 RecordChunk::Normal(c) => {
                     let chunk_slices: Vec<&[u8]> = c.into();
-                    let mut sum = 0;
-                    slices.extend(chunk_slices.into_iter().map(|slice| {
-                        sum += slice.len();
-                        IoSlice::new(slice)
-                    }));
+                    slices.extend(chunk_slices.into_iter().map(IoSlice::new));
                 }
                 RecordChunk::Slop(size) => {
                     slices.push(IoSlice::new(slop.as_slice().split_at(*size).0))
@@ -116,59 +665,134 @@ impl LogManager {
             }
         }
 
-        match buffer.write_all_vectored(slices.as_mut_slice()) {
-            Err(_) => Err(Error::UnableToWriteLogFile(
-                self.current_log_file().to_str().unwrap().to_string(),
-            )),
-            Ok(_) => match buffer.flush() {
-                Err(_) => Err(Error::UnableToWriteLogFile(
-                    self.current_log_file().to_str().unwrap().to_string(),
-                )),
-                _ => Ok(()),
-            },
+        buffer
+            .write_all_vectored(slices.as_mut_slice())
+            .and_then(|_| buffer.flush())
+            .map_err(|_| self.write_error())?;
+
+        if self.sync_mode == WalSyncMode::EveryWrite || group.iter().any(|pending| pending.sync) {
+            buffer.sync_data().map_err(|_| self.write_error())?;
         }
+
+        drop(buffer);
+
+        Ok(())
     }
 
     pub fn log_iterator(&self, log_number: LogNumber) -> Result<LogIterator> {
-        let file_path = self.log_file(log_number);
+        self.open_log_iterator(log_number, self.recovery_mode)
+    }
+
+    /// Reads a log that may still be written, from `position` or from the beginning. A record
+    /// being written at the end of the log is not returned until it is complete.
+    pub fn tail_iterator(
+        &self,
+        log_number: LogNumber,
+        position: Option<LogPosition>,
+    ) -> Result<LogIterator> {
+        let mut iter =
+            self.open_log_iterator(log_number, WalRecoveryMode::TolerateCorruptedTailRecords)?;
+
+        if let Some(position) = position {
+            iter.seek(position)?;
+        }
+
+        Ok(iter)
+    }
+
+    fn open_log_iterator(
+        &self,
+        log_number: LogNumber,
+        recovery_mode: WalRecoveryMode,
+    ) -> Result<LogIterator> {
+        let mut file_path = self.log_file(log_number);
+        let mut file = File::open(file_path.as_path());
+
+        // the log may have been archived since it was listed
+        if file.is_err() && self.archived_log_file(log_number).exists() {
+            file_path = self.archived_log_file(log_number);
+            file = File::open(file_path.as_path());
+        }
+
         let file_name = file_path.to_str().unwrap().to_string();
-        let file =
-            File::open(file_path).map_err(|_| Error::UnableToReadLogFile(file_name.clone()))?;
+        let mut file = file.map_err(|_| Error::UnableToReadLogFile(file_name.clone()))?;
+
+        let header = match LogFileHeader::read(&mut file, file_name.as_str())? {
+            None => None,
+            Some(header) if header.log_number != log_number => {
+                return Err(Error::UnexpectedLogNumber(file_name, header.log_number))
+            }
+            Some(header) if header.comparator_name != self.comparator_name => {
+                return Err(Error::ComparatorMismatch(file_name, header.comparator_name))
+            }
+            header => header,
+        };
 
         Ok(LogIterator::new(
             file_name,
             self.block_size.load(Ordering::SeqCst),
             file,
+            recovery_mode,
+            header.as_ref(),
         ))
     }
 
+    /// Numbers of the live, current and archived logs, in ascending order.
+    pub fn all_log_numbers(&self) -> Result<Vec<LogNumber>> {
+        let mut log_numbers = Self::exist_log_numbers(self.dir())?;
+        log_numbers.extend(Self::archived_log_numbers(self.dir())?);
+        log_numbers.sort_unstable();
+        log_numbers.dedup();
+
+        Ok(log_numbers)
+    }
+
     pub fn get_exist_log_number(&self) -> Result<Vec<LogNumber>> {
-        let entries = read_dir(&self.dir())
-            .map_err(|_| Error::UnableToReadDir(self.dir.to_str().unwrap().to_string()))?
+        let current_log_number = self.current_log_number.load(Ordering::SeqCst);
+
+        Ok(Self::exist_log_numbers(self.dir())?
+            .into_iter()
+            .filter(|n| *n != current_log_number)
+            .collect())
+    }
+
+    pub fn exist_log_numbers(dir: &Path) -> Result<Vec<LogNumber>> {
+        Self::exist_file_numbers(dir, r"^log_(\d+)$")
+    }
+
+    fn exist_file_numbers(dir: &Path, pattern: &str) -> Result<Vec<LogNumber>> {
+        let entries = read_dir(dir)
+            .map_err(|_| Error::UnableToReadDir(dir.to_str().unwrap().to_string()))?
             .collect::<STDResult<Vec<DirEntry>, IOError>>()
-            .map_err(|_| Error::UnableToReadDir(self.dir.to_str().unwrap().to_string()))?;
+            .map_err(|_| Error::UnableToReadDir(dir.to_str().unwrap().to_string()))?;
 
-        let current_log_number = self.current_log_number.load(Ordering::SeqCst);
+        let regex = Regex::new(pattern).unwrap();
 
         Ok(entries
             .into_iter()
             .filter_map(|entry| {
-                let regex = Regex::new(r"^log_(\d+)$").unwrap();
-
-                let result = regex
+                regex
                     .captures(entry.file_name().to_str().unwrap())
                     .map(|result| {
                         result
                             .get(1)
                             .map(|num| num.as_str().parse::<LogNumber>().unwrap())
                     })
-                    .flatten();
-
-                match result {
-                    Some(n) if n == current_log_number => None,
-                    others => others,
-                }
+                    .flatten()
             })
             .collect())
     }
 }
+
+impl Drop for LogManager {
+    fn drop(&mut self) {
+        let flushed = self.flush(self.sync_mode != WalSyncMode::NoSync).is_ok();
+
+        if flushed
+            && self.unlogged.load(Ordering::SeqCst)
+            && !self.skipped_records.load(Ordering::SeqCst)
+        {
+            remove_file(self.dir().join(UNLOGGED_MARKER)).ok();
+        }
+    }
+}
@@ -1,8 +1,11 @@
 mod chunk;
+mod file_header;
+mod group_commit;
 mod log_iterator;
 mod log_manager;
 mod record;
 #[cfg(test)]
 mod tests;
 
+pub use log_iterator::{LogIterator, LogPosition, RecoveryReport};
 pub use log_manager::{LogManager, LogNumber};
@@ -1,7 +1,12 @@
-use crate::format::{decode_usize, encode_usize};
+use crate::format::{decode_usize, decode_usize_checked, encode_usize, CompressionType};
 use crate::logger::chunk::{Chunk, ChunkType, CHUNK_HEAD_SIZE, MIN_CHUNK_SIZE};
+use crate::logger::LogNumber;
 use std::ptr::slice_from_raw_parts;
 
+/// Set in the leading byte of a record followed by another record of the same write, next to
+/// the `CompressionType` of its value.
+pub const CONTINUED_FLAG: u8 = 0x80;
+
 pub enum RecordChunk<'a> {
     Normal(Chunk<'a>),
     Slop(usize),
@@ -10,6 +15,8 @@ pub enum RecordChunk<'a> {
 #[allow(dead_code)]
 #[derive(Clone)]
 pub struct Record<'a> {
+    // only written, the log iterator strips it from the records it returns
+    leading_byte: Option<[u8; 1]>,
     key_size: Box<[u8]>,
     key: &'a [u8],
     value_size: Box<[u8]>,
@@ -20,6 +27,7 @@ pub struct Record<'a> {
 impl<'a> Record<'a> {
     pub fn new(key: &'a [u8], value: &'a [u8]) -> Record<'a> {
         Record {
+            leading_byte: None,
             key_size: encode_usize(key.len()),
             key,
             value_size: encode_usize(value.len()),
@@ -27,12 +35,47 @@ impl<'a> Record<'a> {
         }
     }
 
+    /// A record of the current format, `value` is compressed with `compression`. `continued`
+    /// is set when the next record belongs to the same write.
+    pub fn with_compression(
+        key: &'a [u8],
+        value: &'a [u8],
+        compression: CompressionType,
+        continued: bool,
+    ) -> Record<'a> {
+        let flags = if continued { CONTINUED_FLAG } else { 0 };
+
+        Record {
+            leading_byte: Some([compression as u8 | flags]),
+            ..Record::new(key, value)
+        }
+    }
+
+    /// Whether `slice` holds exactly one record, to be checked before decoding untrusted data.
+    pub fn is_valid(slice: &[u8]) -> bool {
+        let mut rest = slice;
+
+        // the key, then the value
+        for _ in 0..2 {
+            match decode_usize_checked(rest) {
+                Some((size, used)) if size <= rest.len() - used => rest = &rest[used + size..],
+                _ => return false,
+            }
+        }
+
+        rest.is_empty()
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        self.get_data().concat()
+    }
+
     pub fn key_size(&self) -> usize {
         decode_usize(self.key_size.as_ptr()).0
     }
 
     pub fn value_size(&self) -> usize {
-        decode_usize(self.value.as_ptr()).0
+        decode_usize(self.value_size.as_ptr()).0
     }
 
     pub fn len(&self) -> usize {
@@ -50,7 +93,13 @@ impl<'a> Record<'a> {
     }
 
     fn get_data(&self) -> Vec<&[u8]> {
-        let mut data = vec![self.key_size.as_ref()];
+        let mut data = vec![];
+
+        if let Some(leading_byte) = self.leading_byte.as_ref() {
+            data.push(leading_byte.as_slice());
+        }
+
+        data.push(self.key_size.as_ref());
 
         if self.key_size() != 0 {
             data.push(self.key)
@@ -85,18 +134,24 @@ impl<'a> Record<'a> {
         }
     }
 
-    fn get_next_chunk(&self, size: usize, mut pos: usize) -> Option<(Chunk, usize)> {
+    fn get_next_chunk(
+        &self,
+        size: usize,
+        mut pos: usize,
+        log_number: LogNumber,
+    ) -> Option<(Chunk, usize)> {
         assert!(size >= MIN_CHUNK_SIZE);
         let mut data: Vec<&[u8]> = vec![];
         let mut data_size = 0;
 
         let chunk_data_size = size - CHUNK_HEAD_SIZE;
+        let start = pos;
 
         let ty = loop {
             let part = match self.get_next_part(pos) {
                 Some(p) => p,
                 _ => {
-                    break if self.len() <= chunk_data_size {
+                    break if start == 0 {
                         ChunkType::Full
                     } else {
                         ChunkType::Last
@@ -110,11 +165,10 @@ impl<'a> Record<'a> {
                 pos += part.len();
             } else {
                 let part_size = chunk_data_size - data_size;
-                data_size += part_size;
                 data.push(part.split_at(part_size).0);
 
                 pos += part_size;
-                break if pos == data_size {
+                break if start == 0 {
                     ChunkType::First
                 } else {
                     ChunkType::Middle
@@ -125,20 +179,25 @@ impl<'a> Record<'a> {
         if data.is_empty() {
             None
         } else {
-            let chunk = Chunk::new(data, ty);
+            let chunk = Chunk::new(data, ty, log_number);
 
             Some((chunk, pos))
         }
     }
 
-    pub fn get_chunks(&self, first_size: usize, max_size: usize) -> (Vec<RecordChunk>, usize) {
+    pub fn get_chunks(
+        &self,
+        first_size: usize,
+        max_size: usize,
+        log_number: LogNumber,
+    ) -> (Vec<RecordChunk>, usize) {
         let mut chunks = vec![];
 
         let mut left_size = first_size;
 
         let mut pos = 0;
 
-        while let Some((c, new_pos)) = self.get_next_chunk(left_size, pos) {
+        while let Some((c, new_pos)) = self.get_next_chunk(left_size, pos, log_number) {
             let len = c.len();
             assert!(len <= left_size);
             chunks.push(RecordChunk::Normal(c));
@@ -179,6 +238,7 @@ impl<'a> From<&[u8]> for Record<'a> {
         };
 
         Record {
+            leading_byte: None,
             key_size: encode_usize(key_size),
             key,
             value_size: encode_usize(value_size),
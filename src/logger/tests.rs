@@ -1,15 +1,24 @@
-use crate::error::Result;
-use crate::logger::chunk::{Chunk, ChunkType};
+use crate::error::{Error, Result};
+use crate::logger::chunk::{Chunk, ChunkType, CHUNK_HEAD_SIZE};
+use crate::logger::file_header::LogFileHeader;
+use crate::logger::group_commit::{GroupCommitQueue, PendingRecord};
 use crate::logger::record::{Record, RecordChunk};
 use crate::logger::LogManager;
+use crate::{CompressionType, RecoveryReport, WalRecoveryMode, WalSyncMode};
 use bytes::{Bytes, BytesMut};
 use rand::distributions::Alphanumeric;
 use rand::seq::SliceRandom;
 use rand::{random, thread_rng, Rng};
+use std::collections::HashMap;
 use std::convert::TryInto;
 use std::env::temp_dir;
-use std::fs::create_dir;
-use std::sync::Arc;
+use std::fs::{create_dir, File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{sleep, spawn};
+use std::time::Duration;
 
 fn create_random_bytes(size: usize) -> Bytes {
     let rng = &mut rand::thread_rng();
@@ -24,8 +33,9 @@ fn create_random_bytes(size: usize) -> Bytes {
 #[test]
 fn test_chunk_encode() {
     let data = create_random_bytes(10);
-    let chunk = Chunk::new(vec![data.as_ref()], ChunkType::Full);
-    assert!(chunk.check_crc32());
+    let chunk = Chunk::new(vec![data.as_ref()], ChunkType::Full, 1);
+    assert!(chunk.check_crc32(1));
+    assert!(!chunk.check_crc32(2));
     let slices: Vec<&[u8]> = chunk.as_ref().into();
 
     let slice = slices
@@ -37,12 +47,23 @@ fn test_chunk_encode() {
         })
         .freeze();
 
-    let chunk_decode: Chunk = slice.as_ref().into();
+    let chunk_decode: Chunk = slice.as_ref().try_into().unwrap();
 
     assert_eq!(chunk.ty(), chunk_decode.ty());
     assert_eq!(chunk.crc32(), chunk_decode.crc32());
     assert_eq!(chunk.len(), chunk_decode.len());
     assert_eq!(chunk.data_len(), chunk_decode.data_len());
+
+    // the checksum covers the type and the size
+    let mut flipped = slice.to_vec();
+    flipped[6] = ChunkType::First.into();
+    let chunk_flipped: Chunk = flipped.as_slice().try_into().unwrap();
+    assert!(!chunk_flipped.check_crc32(1));
+
+    let mut flipped = slice.to_vec();
+    flipped[4] -= 1;
+    let chunk_flipped: Chunk = flipped.as_slice().try_into().unwrap();
+    assert!(!chunk_flipped.check_crc32(1));
 }
 
 #[test]
@@ -51,7 +72,7 @@ fn test_record_encode() {
     let value = create_random_bytes(10);
 
     let record = Record::new(key.as_ref(), value.as_ref());
-    let chunks = record.get_chunks(4 * 1024, 4 * 1024).0;
+    let chunks = record.get_chunks(4 * 1024, 4 * 1024, 0).0;
     let first_chunk = match chunks.first().unwrap() {
         RecordChunk::Normal(c) => Some(c),
         _ => None,
@@ -84,7 +105,7 @@ fn test_large_record_encode() {
     let value = create_random_bytes(100000);
 
     let record = Record::new(key.as_ref(), value.as_ref());
-    let chunks = record.get_chunks(1024, 4 * 1024).0;
+    let chunks = record.get_chunks(1024, 4 * 1024, 0).0;
     let first_chunk = match chunks.first().unwrap() {
         RecordChunk::Normal(c) => Some(c),
         _ => None,
@@ -146,7 +167,7 @@ fn test_log_manager() {
     tmp_dir.push(format!("komachi_test_log_{}", random::<u16>()));
     create_dir(tmp_dir.clone()).unwrap();
 
-    let manager = LogManager::new(tmp_dir, 0, 4 * 1024).unwrap();
+    let manager = LogManager::new(tmp_dir, 0, 4 * 1024, "test").unwrap();
     manager.freeze_current_file(1).unwrap();
 
     let keys: Vec<u32> = (0..1500).collect();
@@ -154,7 +175,10 @@ fn test_log_manager() {
     for key in keys.iter() {
         let value = create_random_bytes(random::<u8>() as usize);
         manager
-            .insert_record(Record::new(key.to_ne_bytes().as_slice(), value.as_ref()))
+            .insert_record(
+                Record::new(key.to_ne_bytes().as_slice(), value.as_ref()),
+                false,
+            )
             .unwrap();
     }
 
@@ -185,7 +209,7 @@ fn test_log_concurrent() {
     tmp_dir.push(format!("komachi_test_log_{}", random::<u16>()));
     create_dir(tmp_dir.clone()).unwrap();
 
-    let manager = Arc::new(LogManager::new(tmp_dir, 0, 4 * 1024).unwrap());
+    let manager = Arc::new(LogManager::new(tmp_dir, 0, 4 * 1024, "test").unwrap());
 
     let pool = threadpool::ThreadPool::new(72);
 
@@ -202,7 +226,10 @@ fn test_log_concurrent() {
         let value = create_random_bytes(random::<u8>() as usize);
         pool.execute(move || {
             manager_ref
-                .insert_record(Record::new(k.to_ne_bytes().as_slice(), value.as_ref()))
+                .insert_record(
+                    Record::new(k.to_ne_bytes().as_slice(), value.as_ref()),
+                    false,
+                )
                 .unwrap()
         })
     }
@@ -230,3 +257,608 @@ fn test_log_concurrent() {
 
     std::mem::drop(manager);
 }
+
+#[test]
+fn test_log_rotation() {
+    let mut tmp_dir = temp_dir();
+    tmp_dir.push(format!("komachi_test_log_{}", random::<u16>()));
+    create_dir(tmp_dir.clone()).unwrap();
+
+    let mut manager = LogManager::new(tmp_dir, 0, 4 * 1024, "test").unwrap();
+    manager.set_sync_mode(WalSyncMode::EveryWrite);
+
+    let keys: Vec<u32> = (0..300).collect();
+
+    for (index, key) in keys.iter().enumerate() {
+        let value = create_random_bytes(random::<u8>() as usize);
+        manager
+            .insert_record(
+                Record::new(key.to_ne_bytes().as_slice(), value.as_ref()),
+                false,
+            )
+            .unwrap();
+
+        // rotate in the middle of a block
+        if index == 99 {
+            manager.freeze_current_file(1).unwrap();
+        }
+    }
+
+    manager.freeze_current_file(2).unwrap();
+
+    let result_keys = [0, 1]
+        .iter()
+        .flat_map(|log_number| manager.log_iterator(*log_number).unwrap())
+        .map(|item| {
+            item.map(|wrapper| {
+                let record = wrapper.record();
+                let array: [u8; 4] = record.key().try_into().unwrap();
+                u32::from_ne_bytes(array)
+            })
+        })
+        .collect::<Result<Vec<u32>>>()
+        .unwrap();
+
+    assert_eq!(result_keys, keys);
+}
+
+#[test]
+fn test_group_commit() {
+    let queue = Arc::new(GroupCommitQueue::new());
+    let groups = Arc::new(AtomicUsize::new(0));
+    let written = Arc::new(Mutex::new(vec![]));
+    let pool = threadpool::ThreadPool::new(16);
+
+    for key in 0..1600u32 {
+        let queue_ref = queue.clone();
+        let groups_ref = groups.clone();
+        let written_ref = written.clone();
+
+        pool.execute(move || {
+            queue_ref
+                .commit(
+                    PendingRecord {
+                        key: Bytes::copy_from_slice(key.to_ne_bytes().as_slice()),
+                        value: Bytes::new(),
+                        sync: false,
+                        continued: false,
+                    },
+                    |group| {
+                        groups_ref.fetch_add(1, Ordering::SeqCst);
+                        sleep(Duration::from_micros(100));
+                        written_ref
+                            .lock()
+                            .unwrap()
+                            .extend(group.iter().map(|pending| {
+                                let array: [u8; 4] = pending.key.as_ref().try_into().unwrap();
+                                u32::from_ne_bytes(array)
+                            }));
+
+                        Ok(())
+                    },
+                )
+                .unwrap();
+        });
+    }
+
+    pool.join();
+
+    let mut written_keys = written.lock().unwrap().clone();
+    written_keys.sort_unstable();
+
+    assert_eq!(written_keys, (0..1600).collect::<Vec<u32>>());
+    assert!(groups.load(Ordering::SeqCst) < 1600);
+}
+
+#[test]
+fn test_group_commit_failure() {
+    let queue = Arc::new(GroupCommitQueue::new());
+    let record = |key: u8| PendingRecord {
+        key: Bytes::copy_from_slice(&[key]),
+        value: Bytes::new(),
+        sync: false,
+        continued: false,
+    };
+    let commit = |key: u8, fail: fn() -> Result<()>| {
+        let queue_ref = queue.clone();
+
+        spawn(move || queue_ref.commit(record(key), |_| fail()))
+    };
+
+    // the writers queued behind a slow group are written in the next one, which fails
+    let slow = commit(0, || {
+        sleep(Duration::from_millis(100));
+        Ok(())
+    });
+    sleep(Duration::from_millis(20));
+    let failed = (1..3)
+        .map(|key| commit(key, || Err(Error::InvalidChunk)))
+        .collect::<Vec<_>>();
+
+    assert!(slow.join().unwrap().is_ok());
+    for handle in failed {
+        assert!(matches!(handle.join().unwrap(), Err(Error::InvalidChunk)));
+    }
+
+    // a leader panicking fails the group without blocking the next writers
+    let slow = commit(0, || {
+        sleep(Duration::from_millis(100));
+        Ok(())
+    });
+    sleep(Duration::from_millis(20));
+    let panicked = (1..3)
+        .map(|key| commit(key, || panic!("write failed")))
+        .collect::<Vec<_>>();
+
+    assert!(slow.join().unwrap().is_ok());
+    let results = panicked
+        .into_iter()
+        .map(|handle| handle.join())
+        .collect::<Vec<_>>();
+    assert_eq!(results.iter().filter(|result| result.is_err()).count(), 1);
+    assert!(results
+        .iter()
+        .any(|result| matches!(result, Ok(Err(Error::GroupCommitAborted)))));
+
+    assert!(queue.commit(record(3), |_| Ok(())).is_ok());
+}
+
+fn create_corrupted_log<F: Fn(&mut std::fs::File)>(corrupt: F) -> PathBuf {
+    let mut tmp_dir = temp_dir();
+    tmp_dir.push(format!("komachi_test_log_{}", random::<u16>()));
+    create_dir(tmp_dir.clone()).unwrap();
+
+    let manager = LogManager::new(tmp_dir.clone(), 0, 4 * 1024, "test").unwrap();
+    let value = create_random_bytes(100);
+
+    for key in 0..200u32 {
+        manager
+            .insert_record(
+                Record::new(key.to_ne_bytes().as_slice(), value.as_ref()),
+                false,
+            )
+            .unwrap();
+    }
+
+    manager.freeze_current_file(1).unwrap();
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(tmp_dir.join("log_0"))
+        .unwrap();
+    corrupt(&mut file);
+
+    tmp_dir
+}
+
+fn recover(dir: PathBuf, recovery_mode: WalRecoveryMode) -> (Result<Vec<u32>>, RecoveryReport) {
+    let mut manager = LogManager::new(dir, 1, 4 * 1024, "test").unwrap();
+    manager.set_recovery_mode(recovery_mode);
+
+    let mut iter = manager.log_iterator(0).unwrap();
+    let keys = iter
+        .by_ref()
+        .map(|item| {
+            item.map(|wrapper| {
+                let array: [u8; 4] = wrapper.record().key().try_into().unwrap();
+                u32::from_ne_bytes(array)
+            })
+        })
+        .collect::<Result<Vec<u32>>>();
+
+    (keys, iter.report().clone())
+}
+
+#[test]
+fn test_recover_truncated_tail() {
+    let truncate = |file: &mut std::fs::File| {
+        let len = file.metadata().unwrap().len();
+        file.set_len(len - 50).unwrap();
+    };
+
+    let (keys, report) = recover(
+        create_corrupted_log(truncate),
+        WalRecoveryMode::TolerateCorruptedTailRecords,
+    );
+    assert_eq!(keys.unwrap(), (0..199).collect::<Vec<u32>>());
+    assert!(report.dropped_bytes > 0);
+
+    let (keys, _) = recover(
+        create_corrupted_log(truncate),
+        WalRecoveryMode::AbsoluteConsistency,
+    );
+    assert!(keys.is_err());
+
+    let (keys, report) = recover(
+        create_corrupted_log(truncate),
+        WalRecoveryMode::PointInTimeRecovery,
+    );
+    assert_eq!(keys.unwrap().len(), 199);
+    assert!(report.stopped_early);
+}
+
+#[test]
+fn test_recover_corrupted_record() {
+    let corrupt = |file: &mut std::fs::File| {
+        file.seek(SeekFrom::Start(5000)).unwrap();
+        file.write_all(&[0xff; 8]).unwrap();
+    };
+
+    let (keys, report) = recover(
+        create_corrupted_log(corrupt),
+        WalRecoveryMode::SkipAnyCorruptedRecords,
+    );
+    let keys = keys.unwrap();
+    assert!(keys.len() < 200);
+    assert_eq!(keys.last(), Some(&199));
+    assert!(report.dropped_bytes > 0);
+    assert!(!report.stopped_early);
+
+    let (keys, report) = recover(
+        create_corrupted_log(corrupt),
+        WalRecoveryMode::PointInTimeRecovery,
+    );
+    let keys = keys.unwrap();
+    assert_eq!(keys, (0..keys.len() as u32).collect::<Vec<u32>>());
+    assert!(keys.len() < 50);
+    assert!(report.stopped_early);
+
+    for recovery_mode in [
+        WalRecoveryMode::TolerateCorruptedTailRecords,
+        WalRecoveryMode::AbsoluteConsistency,
+    ]
+    .iter()
+    {
+        let (keys, _) = recover(create_corrupted_log(corrupt), *recovery_mode);
+        assert!(keys.is_err());
+    }
+}
+
+#[test]
+fn test_recover_corrupted_chunk_head() {
+    let first_chunk = LogFileHeader::new(0, "test").encode().len() as u64;
+
+    for (offset, byte) in [(6, ChunkType::First.into()), (4, 0xff), (5, 0xff)].iter() {
+        let corrupt = |file: &mut std::fs::File| {
+            file.seek(SeekFrom::Start(first_chunk + offset)).unwrap();
+            file.write_all(&[*byte]).unwrap();
+        };
+
+        let (keys, report) = recover(
+            create_corrupted_log(corrupt),
+            WalRecoveryMode::SkipAnyCorruptedRecords,
+        );
+        let keys = keys.unwrap();
+        assert!(!keys.contains(&0));
+        assert_eq!(keys.last(), Some(&199));
+        assert!(report.dropped_bytes > 0);
+
+        let (keys, _) = recover(
+            create_corrupted_log(corrupt),
+            WalRecoveryMode::AbsoluteConsistency,
+        );
+        assert!(keys.is_err());
+    }
+}
+
+#[test]
+fn test_log_file_header() {
+    let header = LogFileHeader::new(7, "test");
+    let mut tmp_dir = temp_dir();
+    tmp_dir.push(format!("komachi_test_log_{}", random::<u16>()));
+    create_dir(tmp_dir.clone()).unwrap();
+
+    let path = tmp_dir.join("log_7");
+    std::fs::write(&path, header.encode()).unwrap();
+    let decoded = LogFileHeader::read(&mut File::open(&path).unwrap(), "log_7")
+        .unwrap()
+        .unwrap();
+    assert_eq!(decoded, header);
+
+    let unknown = LogFileHeader {
+        format_version: 2,
+        ..header
+    };
+    std::fs::write(&path, unknown.encode()).unwrap();
+    assert!(matches!(
+        LogFileHeader::read(&mut File::open(&path).unwrap(), "log_7"),
+        Err(Error::UnsupportedLogFormat(_, 2))
+    ));
+
+    let dir = create_corrupted_log(|_| ());
+    let manager = LogManager::new(dir, 1, 4 * 1024, "other").unwrap();
+    assert!(matches!(
+        manager.log_iterator(0),
+        Err(Error::ComparatorMismatch(_, _))
+    ));
+}
+
+#[test]
+fn test_read_legacy_log() {
+    let mut dir = temp_dir();
+    dir.push(format!("komachi_test_log_{}", random::<u16>()));
+    create_dir(dir.clone()).unwrap();
+
+    // unsalted chunks in native byte order with a shorter head and without a header
+    let value = create_random_bytes(100);
+    let block_size = 4 * 1024;
+    let head_size = 7;
+    let mut bytes = vec![];
+
+    for key in 0..200u32 {
+        let key = key.to_ne_bytes();
+        let record = Record::new(key.as_slice(), value.as_ref());
+        let (chunks, _) = record.get_chunks(block_size, block_size, 0);
+        let data = match &chunks[0] {
+            RecordChunk::Normal(c) => c.data.concat(),
+            RecordChunk::Slop(_) => unreachable!(),
+        };
+        let mut pos = 0;
+
+        while pos < data.len() {
+            let left = block_size - bytes.len() % block_size;
+
+            if left < head_size * 2 {
+                bytes.extend(vec![0; left]);
+                continue;
+            }
+
+            let size = (left - head_size).min(data.len() - pos);
+            let ty = match (pos == 0, pos + size == data.len()) {
+                (true, true) => ChunkType::Full,
+                (true, false) => ChunkType::First,
+                (false, false) => ChunkType::Middle,
+                (false, true) => ChunkType::Last,
+            };
+            let part = &data[pos..pos + size];
+
+            bytes.extend_from_slice(crc32fast::hash(part).to_ne_bytes().as_slice());
+            bytes.extend_from_slice((size as u16).to_ne_bytes().as_slice());
+            bytes.push(ty.into());
+            bytes.extend_from_slice(part);
+            pos += size;
+        }
+    }
+
+    std::fs::write(dir.join("log_0"), bytes).unwrap();
+
+    let (keys, report) = recover(dir, WalRecoveryMode::AbsoluteConsistency);
+    assert_eq!(keys.unwrap(), (0..200).collect::<Vec<u32>>());
+    assert_eq!(report.dropped_bytes, 0);
+}
+
+#[test]
+fn test_recycle_log_file() {
+    let mut tmp_dir = temp_dir();
+    tmp_dir.push(format!("komachi_test_log_{}", random::<u16>()));
+    create_dir(tmp_dir.clone()).unwrap();
+
+    let mut manager = LogManager::new(tmp_dir.clone(), 0, 4 * 1024, "test").unwrap();
+    manager.set_recycle_log_file_num(1);
+    manager.set_preallocate_size(Some(1024 * 1024));
+    let value = create_random_bytes(100);
+
+    for key in 0..200u32 {
+        manager
+            .insert_record(
+                Record::new(key.to_ne_bytes().as_slice(), value.as_ref()),
+                false,
+            )
+            .unwrap();
+    }
+
+    manager.freeze_current_file(1).unwrap();
+    manager.truncate_log(0).unwrap();
+    assert_eq!(manager.get_exist_log_number().unwrap(), vec![]);
+    assert_eq!(manager.recycled_log_numbers(), vec![0]);
+
+    manager.freeze_current_file(2).unwrap();
+    manager.truncate_log(1).unwrap();
+    assert_eq!(manager.recycled_log_numbers(), vec![1]);
+
+    for key in 0..3u32 {
+        manager
+            .insert_record(
+                Record::new(key.to_ne_bytes().as_slice(), value.as_ref()),
+                false,
+            )
+            .unwrap();
+    }
+
+    manager.freeze_current_file(3).unwrap();
+    manager.set_recovery_mode(WalRecoveryMode::AbsoluteConsistency);
+
+    let mut iter = manager.log_iterator(2).unwrap();
+    let keys = iter
+        .by_ref()
+        .map(|item| {
+            let array: [u8; 4] = item.unwrap().record().key().try_into().unwrap();
+            u32::from_ne_bytes(array)
+        })
+        .collect::<Vec<u32>>();
+
+    assert_eq!(keys, vec![0, 1, 2]);
+    assert_eq!(iter.report(), &RecoveryReport::default());
+
+    manager.truncate_log(2).unwrap();
+    let reopened = LogManager::new(tmp_dir, 4, 4 * 1024, "test").unwrap();
+    assert_eq!(reopened.recycled_log_numbers(), vec![2]);
+}
+
+#[test]
+fn test_short_chunk_at_end() {
+    let mut tmp_dir = temp_dir();
+    tmp_dir.push(format!("komachi_test_log_{}", random::<u16>()));
+    create_dir(tmp_dir.clone()).unwrap();
+
+    let mut manager = LogManager::new(tmp_dir, 0, 4 * 1024, "test").unwrap();
+    manager.set_recovery_mode(WalRecoveryMode::AbsoluteConsistency);
+
+    // the first chunk fills the block, the last one holds a single byte at the end of the file
+    let key = 0u32.to_ne_bytes();
+    let value = create_random_bytes(4 * 1024 - CHUNK_HEAD_SIZE - 6);
+    manager
+        .insert_record(Record::new(key.as_slice(), value.as_ref()), false)
+        .unwrap();
+    manager.freeze_current_file(1).unwrap();
+
+    let mut iter = manager.log_iterator(0).unwrap();
+    let values = iter
+        .by_ref()
+        .map(|item| Bytes::copy_from_slice(item.unwrap().record().value()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(values, vec![value]);
+    assert_eq!(iter.report(), &RecoveryReport::default());
+}
+
+#[test]
+fn test_recover_corrupted_recycled_log() {
+    let mut tmp_dir = temp_dir();
+    tmp_dir.push(format!("komachi_test_log_{}", random::<u16>()));
+    create_dir(tmp_dir.clone()).unwrap();
+
+    let mut manager = LogManager::new(tmp_dir.clone(), 0, 4 * 1024, "test").unwrap();
+    manager.set_recycle_log_file_num(1);
+    let value = create_random_bytes(100);
+    let insert = |manager: &LogManager, keys: std::ops::Range<u32>| {
+        for key in keys {
+            manager
+                .insert_record(
+                    Record::new(key.to_ne_bytes().as_slice(), value.as_ref()),
+                    false,
+                )
+                .unwrap();
+        }
+    };
+
+    insert(&manager, 0..200);
+    manager.freeze_current_file(1).unwrap();
+    manager.truncate_log(0).unwrap();
+
+    // the log 2 reuses the file of the log 0
+    manager.freeze_current_file(2).unwrap();
+    insert(&manager, 0..50);
+    manager.freeze_current_file(3).unwrap();
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(tmp_dir.join("log_2"))
+        .unwrap();
+    file.seek(SeekFrom::Start(3000)).unwrap();
+    file.write_all(&[0xff]).unwrap();
+
+    let read = |manager: &mut LogManager, recovery_mode| {
+        manager.set_recovery_mode(recovery_mode);
+        let mut iter = manager.log_iterator(2).unwrap();
+        let keys = iter
+            .by_ref()
+            .map(|item| {
+                item.map(|wrapper| {
+                    let array: [u8; 4] = wrapper.record().key().try_into().unwrap();
+                    u32::from_ne_bytes(array)
+                })
+            })
+            .collect::<Result<Vec<u32>>>();
+
+        (keys, iter.report().clone())
+    };
+
+    let (keys, report) = read(&mut manager, WalRecoveryMode::SkipAnyCorruptedRecords);
+    let keys = keys.unwrap();
+    assert!(keys.len() < 50);
+    assert_eq!(keys.last(), Some(&49));
+    assert!(report.dropped_bytes > 0);
+
+    let (keys, _) = read(&mut manager, WalRecoveryMode::AbsoluteConsistency);
+    assert!(keys.is_err());
+}
+
+#[test]
+fn test_record_compression() {
+    let mut tmp_dir = temp_dir();
+    tmp_dir.push(format!("komachi_test_log_{}", random::<u16>()));
+    create_dir(tmp_dir.clone()).unwrap();
+
+    let mut manager = LogManager::new(tmp_dir.clone(), 0, 4 * 1024, "test").unwrap();
+    manager.set_compression(CompressionType::Snappy);
+
+    let values: Vec<Bytes> = (0..100u32)
+        .map(|key| match key % 2 {
+            0 => Bytes::from(vec![key as u8; 10 * 1024]),
+            _ => create_random_bytes(100),
+        })
+        .collect();
+
+    for (key, value) in values.iter().enumerate() {
+        manager
+            .insert_record(
+                Record::new((key as u32).to_ne_bytes().as_slice(), value.as_ref()),
+                false,
+            )
+            .unwrap();
+    }
+
+    manager.freeze_current_file(1).unwrap();
+
+    let read_values = manager
+        .log_iterator(0)
+        .unwrap()
+        .map(|item| Bytes::copy_from_slice(item.unwrap().record().value()))
+        .collect::<Vec<Bytes>>();
+    assert_eq!(read_values, values);
+
+    let file_size = tmp_dir.join("log_0").metadata().unwrap().len() as usize;
+    assert!(file_size < 100 * 1024);
+}
+
+#[test]
+fn test_archive_log() {
+    let mut tmp_dir = temp_dir();
+    tmp_dir.push(format!("komachi_test_log_{}", random::<u16>()));
+    create_dir(tmp_dir.clone()).unwrap();
+
+    let mut manager = LogManager::new(tmp_dir.clone(), 0, 4 * 1024, "test").unwrap();
+    manager.set_archive(None, Some(30 * 1024));
+    let value = create_random_bytes(100);
+
+    for log_number in 0..3 {
+        for key in 0..100u32 {
+            manager
+                .insert_record(
+                    Record::new(key.to_ne_bytes().as_slice(), value.as_ref()),
+                    false,
+                )
+                .unwrap();
+        }
+
+        manager.freeze_current_file(log_number + 1).unwrap();
+        manager.truncate_log(log_number).unwrap();
+        manager.purge_archive(&HashMap::new()).unwrap();
+    }
+
+    // each log takes about 11KB, the oldest one does not fit in the limit
+    assert_eq!(manager.get_exist_log_number().unwrap(), vec![]);
+    assert_eq!(
+        LogManager::archived_log_numbers(tmp_dir.as_path()).unwrap(),
+        vec![1, 2]
+    );
+    assert_eq!(manager.all_log_numbers().unwrap(), vec![1, 2, 3]);
+    assert_eq!(manager.log_iterator(1).unwrap().count(), 100);
+
+    // a pinned log outlives the ttl until it is released
+    manager.set_archive(Some(Duration::from_secs(0)), None);
+    manager
+        .purge_archive(&[(2, 1)].iter().cloned().collect::<HashMap<_, _>>())
+        .unwrap();
+    assert_eq!(
+        LogManager::archived_log_numbers(tmp_dir.as_path()).unwrap(),
+        vec![2]
+    );
+
+    manager.purge_archive(&HashMap::new()).unwrap();
+    assert_eq!(
+        LogManager::archived_log_numbers(tmp_dir.as_path()).unwrap(),
+        vec![]
+    );
+}
@@ -0,0 +1,58 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+const PROBES: u64 = 6;
+
+/// A bloom filter over the user keys of a memtable, or over their first `prefix_length` bytes.
+/// Keys are added concurrently by the writers.
+pub struct BloomFilter {
+    bits: Box<[AtomicU64]>,
+    prefix_length: Option<usize>,
+}
+
+#[allow(dead_code)]
+impl BloomFilter {
+    pub fn new(bits: usize, prefix_length: Option<usize>) -> Self {
+        BloomFilter {
+            bits: (0..bits.div_ceil(64).max(1))
+                .map(|_| AtomicU64::new(0))
+                .collect(),
+            prefix_length,
+        }
+    }
+
+    // the bits of a key are derived from two halves of its hash
+    fn probes(&self, key: &[u8]) -> impl Iterator<Item = u64> {
+        let key = match self.prefix_length {
+            Some(prefix_length) if prefix_length < key.len() => &key[..prefix_length],
+            _ => key,
+        };
+
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        let bit_count = self.bits.len() as u64 * 64;
+        let delta = (hash >> 32) | 1;
+
+        (0..PROBES).map(move |probe| hash.wrapping_add(probe.wrapping_mul(delta)) % bit_count)
+    }
+
+    pub fn add(&self, key: &[u8]) {
+        for bit in self.probes(key) {
+            self.bits[(bit / 64) as usize].fetch_or(1 << (bit % 64), Ordering::Relaxed);
+        }
+    }
+
+    /// `false` when the key was never added.
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        self.probes(key).all(|bit| {
+            self.bits[(bit / 64) as usize].load(Ordering::Relaxed) & (1 << (bit % 64)) != 0
+        })
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.bits.len() * 8
+    }
+}
@@ -0,0 +1,123 @@
+use crate::memtable::rep::{MemTableRep, MemTableRepIterator, SkipListSettings};
+use crate::memtable::InternalKey;
+use crate::skip_list::{RangeEstimate, SkipList};
+use crate::Comparator;
+use bytes::Bytes;
+use std::cmp::{min, Ordering};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::OnceLock;
+
+/// Spreads the entries over buckets by the prefix of their user key, each bucket being a skip
+/// list created on its first insert. The lookups of a key only search its bucket, while a
+/// total order iteration has to sort the entries of every bucket.
+pub struct HashPrefixRep<K: Comparator> {
+    prefix_length: usize,
+    // every bucket is built with the same settings, the seeded ones draw the same levels
+    skip_list: SkipListSettings,
+    buckets: Box<[OnceLock<SkipList<K>>]>,
+}
+
+#[allow(dead_code)]
+impl<K: Comparator> HashPrefixRep<K> {
+    pub fn new(prefix_length: usize, bucket_count: usize, skip_list: SkipListSettings) -> Self {
+        HashPrefixRep {
+            prefix_length,
+            skip_list,
+            buckets: (0..bucket_count.max(1)).map(|_| OnceLock::new()).collect(),
+        }
+    }
+
+    fn bucket_index(&self, key: &[u8]) -> usize {
+        let user_key = InternalKey::split_key(key);
+        let mut hasher = DefaultHasher::new();
+        user_key[..min(self.prefix_length, user_key.len())].hash(&mut hasher);
+
+        hasher.finish() as usize % self.buckets.len()
+    }
+
+    fn bucket(&self, key: &[u8]) -> Option<&SkipList<K>> {
+        self.buckets[self.bucket_index(key)].get()
+    }
+
+    fn sorted_entries(&self) -> Vec<(&[u8], &[u8])> {
+        let mut entries = self
+            .buckets
+            .iter()
+            .filter_map(|bucket| bucket.get())
+            .flat_map(|bucket| bucket.iter())
+            .collect::<Vec<_>>();
+        entries.sort_unstable_by(|(a, _), (b, _)| K::compare(a, b));
+
+        entries
+    }
+}
+
+impl<K: Comparator> MemTableRep for HashPrefixRep<K> {
+    fn insert(&self, key: Bytes, value: Bytes) {
+        self.buckets[self.bucket_index(key.as_ref())]
+            .get_or_init(|| self.skip_list.new_skip_list())
+            .insert(key, value)
+    }
+
+    fn insert_with_hint(&self, key: Bytes, value: Bytes) {
+        self.buckets[self.bucket_index(key.as_ref())]
+            .get_or_init(|| self.skip_list.new_skip_list())
+            .insert_with_hint(key, value)
+    }
+
+    fn update_value(
+        &self,
+        key: &[u8],
+        update: &mut dyn FnMut(&mut [u8], usize) -> Option<usize>,
+    ) -> bool {
+        self.bucket(key)
+            .is_some_and(|bucket| bucket.update_value(key, update))
+    }
+
+    fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        self.bucket(key)
+            .and_then(|bucket| MemTableRep::get(bucket, key))
+    }
+
+    fn seek_less_or_equal(&self, key: &[u8]) -> Option<(&[u8], &[u8])> {
+        // every version of a user key lands in the same bucket
+        self.bucket(key)
+            .and_then(|bucket| bucket.seek_less_or_equal(key))
+    }
+
+    fn iter(&self) -> MemTableRepIterator<'_> {
+        Box::new(self.sorted_entries().into_iter())
+    }
+
+    fn iter_from(&self, key: &[u8]) -> MemTableRepIterator<'_> {
+        let entries = self.sorted_entries();
+        let start = entries.partition_point(|(entry, _)| K::compare(entry, key) == Ordering::Less);
+
+        Box::new(entries.into_iter().skip(start))
+    }
+
+    fn estimate_range(&self, begin: &[u8], end: &[u8]) -> RangeEstimate {
+        self.buckets
+            .iter()
+            .filter_map(|bucket| bucket.get())
+            .map(|bucket| bucket.estimate_range(begin, end))
+            .fold(RangeEstimate::default(), |sum, estimate| sum + estimate)
+    }
+
+    fn len(&self) -> usize {
+        self.buckets
+            .iter()
+            .filter_map(|bucket| bucket.get())
+            .map(|bucket| bucket.len())
+            .sum()
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.buckets
+            .iter()
+            .filter_map(|bucket| bucket.get())
+            .map(|bucket| bucket.memory_usage())
+            .sum()
+    }
+}
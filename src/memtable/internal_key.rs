@@ -16,6 +16,14 @@ impl InternalKey {
         InternalKey { key, value_tag }
     }
 
+    pub fn key(&self) -> &Bytes {
+        &self.key
+    }
+
+    pub fn value_tag(&self) -> ValueTag {
+        self.value_tag
+    }
+
     pub fn split_key(slice: &[u8]) -> &[u8] {
         Self::split(slice).1
     }
@@ -32,6 +40,31 @@ impl InternalKey {
 
         (wrapped_tag.into(), key)
     }
+
+    /// Splits an internal key read from a legacy log, whose tag is in native byte order.
+    pub fn split_legacy(slice: &[u8]) -> (ValueTag, &[u8]) {
+        let (tag, key) = slice.split_at(size_of::<WrappedValueTag>());
+
+        let mut wrapped_tag: WrappedValueTag = Default::default();
+        wrapped_tag.copy_from_slice(&tag[0..size_of::<WrappedValueTag>()]);
+
+        (ValueTag::from_legacy(wrapped_tag), key)
+    }
+
+    /// Reads an internal key from a log, in the byte order of its format.
+    pub fn from_logged(slice: &[u8], legacy: bool) -> Self {
+        let (tag, key) = if legacy {
+            Self::split_legacy(slice)
+        } else {
+            Self::split(slice)
+        };
+
+        InternalKey {
+            key: Bytes::copy_from_slice(key),
+            value_tag: tag,
+        }
+    }
+
     pub fn as_bytes(&self) -> Bytes {
         let wrapped_tag: WrappedValueTag = self.value_tag.into();
         let mut result = BytesMut::from(wrapped_tag.to_vec().as_slice());
@@ -71,4 +104,8 @@ impl<C: Comparator> Comparator for InternalKeyComparator<C> {
             others => others,
         }
     }
+
+    fn name() -> &'static str {
+        C::name()
+    }
 }
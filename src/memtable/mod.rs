@@ -1,8 +1,15 @@
+mod bloom_filter;
+mod hash_prefix_rep;
 pub mod internal_key;
+mod range_tombstone;
+mod rep;
 mod table;
+mod vector_rep;
 
 #[cfg(test)]
 mod tests;
 
 pub use internal_key::InternalKey;
+pub use range_tombstone::RangeTombstone;
+pub use rep::{new_rep, SkipListSettings};
 pub use table::{MemTable, MemTableMut};
@@ -0,0 +1,77 @@
+use crate::format::SequenceNumber;
+use crate::Comparator;
+use bytes::Bytes;
+use std::cmp::Ordering;
+use std::marker::PhantomData;
+use std::mem::size_of;
+use std::sync::RwLock;
+
+/// Deletes the versions of the keys in `[begin, end)` older than `sequence`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RangeTombstone {
+    pub begin: Bytes,
+    pub end: Bytes,
+    pub sequence: SequenceNumber,
+}
+
+impl RangeTombstone {
+    pub fn covers<C: Comparator>(&self, key: &[u8]) -> bool {
+        C::compare(self.begin.as_ref(), key) != Ordering::Greater
+            && C::compare(key, self.end.as_ref()) == Ordering::Less
+    }
+}
+
+/// The range tombstones of a memtable, kept apart from its point keys. Range deletions are
+/// rare, so they are scanned linearly.
+pub struct RangeTombstones<C: Comparator> {
+    tombstones: RwLock<Vec<RangeTombstone>>,
+    _key_comparator: PhantomData<C>,
+}
+
+#[allow(dead_code)]
+impl<C: Comparator> RangeTombstones<C> {
+    pub fn new() -> Self {
+        RangeTombstones {
+            tombstones: RwLock::new(vec![]),
+            _key_comparator: PhantomData,
+        }
+    }
+
+    pub fn add(&self, tombstone: RangeTombstone) {
+        self.tombstones.write().unwrap().push(tombstone);
+    }
+
+    pub fn all(&self) -> Vec<RangeTombstone> {
+        self.tombstones.read().unwrap().clone()
+    }
+
+    /// The sequence number of the newest tombstone covering `key` not newer than `sequence`.
+    pub fn max_covering(&self, key: &[u8], sequence: SequenceNumber) -> Option<SequenceNumber> {
+        self.tombstones
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|tombstone| tombstone.sequence <= sequence && tombstone.covers::<C>(key))
+            .map(|tombstone| tombstone.sequence)
+            .max()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tombstones.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.tombstones
+            .read()
+            .unwrap()
+            .iter()
+            .map(|tombstone| {
+                tombstone.begin.len() + tombstone.end.len() + size_of::<RangeTombstone>()
+            })
+            .sum()
+    }
+}
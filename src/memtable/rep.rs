@@ -0,0 +1,190 @@
+use crate::memtable::hash_prefix_rep::HashPrefixRep;
+use crate::memtable::vector_rep::VectorRep;
+use crate::skip_list::{
+    max_level_for_entries, LevelGenerator, RandomLevelGenerator, RangeEstimate,
+    SeededLevelGenerator, SkipList, XorShiftLevelGenerator,
+};
+use crate::{Comparator, LevelGeneratorType, MemTableRepType, Options};
+use bytes::Bytes;
+
+pub type MemTableRepIterator<'a> = Box<dyn Iterator<Item = (&'a [u8], &'a [u8])> + 'a>;
+
+/// The ordered structure holding the entries of a memtable. The keys are encoded internal keys
+/// ordered by the comparator the representation is created with. An entry is never removed once
+/// inserted, and only `update_value` modifies its value.
+pub trait MemTableRep: Send + Sync {
+    /// Inserting a key that is already present keeps the first value.
+    fn insert(&self, key: Bytes, value: Bytes);
+
+    /// Inserts like `insert`, the representations that can reuse the position of the previous
+    /// insert of the thread do so.
+    fn insert_with_hint(&self, key: Bytes, value: Bytes) {
+        self.insert(key, value)
+    }
+
+    /// Lets `update` modify the value of the entry whose key is equal to `key` in place. `update`
+    /// gets the room the value was first written to and the current length of the value, and
+    /// returns the new length, which can be up to the size of the room. `false` when the key is
+    /// not found, the value is left unchanged, or the representation cannot update values.
+    fn update_value(
+        &self,
+        _key: &[u8],
+        _update: &mut dyn FnMut(&mut [u8], usize) -> Option<usize>,
+    ) -> bool {
+        false
+    }
+
+    /// The value of the entry whose key is equal to `key`.
+    fn get(&self, key: &[u8]) -> Option<&[u8]>;
+
+    /// The last entry whose key is less than or equal to `key`.
+    fn seek_less_or_equal(&self, key: &[u8]) -> Option<(&[u8], &[u8])>;
+
+    fn iter(&self) -> MemTableRepIterator<'_>;
+
+    /// Iterates from the first entry whose key is greater than or equal to `key`.
+    fn iter_from(&self, key: &[u8]) -> MemTableRepIterator<'_>;
+
+    /// The entries whose keys are in `[begin, end)`, estimated without visiting all of them
+    /// when the representation allows it.
+    fn estimate_range(&self, begin: &[u8], end: &[u8]) -> RangeEstimate;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn memory_usage(&self) -> usize;
+
+    /// Called when the memtable becomes immutable, nothing is inserted after it.
+    fn freeze(&mut self) {}
+}
+
+// the probability for a node to reach the next level
+const LEVEL_PROBABILITY: f32 = 0.1;
+const DEFAULT_MAX_LEVEL: usize = 10;
+
+/// How the skip lists of a memtable are built.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SkipListSettings {
+    pub level_generator: LevelGeneratorType,
+    pub max_level: usize,
+    pub block_size: usize,
+}
+
+impl Default for SkipListSettings {
+    fn default() -> Self {
+        SkipListSettings {
+            level_generator: LevelGeneratorType::Random,
+            max_level: DEFAULT_MAX_LEVEL,
+            block_size: 4 * 1024,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl SkipListSettings {
+    pub fn from_options(options: &Options) -> Self {
+        SkipListSettings {
+            level_generator: options.memtable_level_generator,
+            max_level: options
+                .memtable_expected_entries
+                .map_or(DEFAULT_MAX_LEVEL, |entries| {
+                    max_level_for_entries(entries, LEVEL_PROBABILITY)
+                }),
+            block_size: options.arena_block_size,
+        }
+    }
+
+    pub fn level_generator(&self) -> Box<dyn LevelGenerator> {
+        match self.level_generator {
+            LevelGeneratorType::Random => {
+                Box::new(RandomLevelGenerator::new(self.max_level, LEVEL_PROBABILITY))
+            }
+            LevelGeneratorType::Seeded { seed } => Box::new(SeededLevelGenerator::new(
+                self.max_level,
+                LEVEL_PROBABILITY,
+                seed,
+            )),
+            LevelGeneratorType::XorShift => Box::new(XorShiftLevelGenerator::new(
+                self.max_level,
+                LEVEL_PROBABILITY,
+            )),
+        }
+    }
+
+    pub(crate) fn new_skip_list<K: Comparator>(&self) -> SkipList<K> {
+        SkipList::new(self.level_generator(), self.block_size)
+    }
+}
+
+pub fn new_rep<K: Comparator>(
+    rep_type: MemTableRepType,
+    skip_list: SkipListSettings,
+) -> Box<dyn MemTableRep> {
+    match rep_type {
+        MemTableRepType::SkipList => Box::new(skip_list.new_skip_list::<K>()),
+        MemTableRepType::HashPrefix {
+            prefix_length,
+            bucket_count,
+        } => Box::new(HashPrefixRep::<K>::new(
+            prefix_length,
+            bucket_count,
+            skip_list,
+        )),
+        MemTableRepType::Vector => Box::new(VectorRep::<K>::new()),
+    }
+}
+
+impl<K: Comparator> MemTableRep for SkipList<K> {
+    fn insert(&self, key: Bytes, value: Bytes) {
+        SkipList::insert(self, key, value)
+    }
+
+    fn insert_with_hint(&self, key: Bytes, value: Bytes) {
+        SkipList::insert_with_hint(self, key, value)
+    }
+
+    fn update_value(
+        &self,
+        key: &[u8],
+        update: &mut dyn FnMut(&mut [u8], usize) -> Option<usize>,
+    ) -> bool {
+        SkipList::update_value(self, key, update)
+    }
+
+    fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        let mut visitor = self.visitor();
+        visitor.seek(key);
+
+        visitor.value()
+    }
+
+    fn seek_less_or_equal(&self, key: &[u8]) -> Option<(&[u8], &[u8])> {
+        let mut visitor = self.visitor();
+        visitor.seek_less_or_equal(key);
+
+        visitor.key().zip(visitor.value())
+    }
+
+    fn iter(&self) -> MemTableRepIterator<'_> {
+        Box::new(SkipList::iter(self))
+    }
+
+    fn iter_from(&self, key: &[u8]) -> MemTableRepIterator<'_> {
+        Box::new(SkipList::iter_from(self, key))
+    }
+
+    fn estimate_range(&self, begin: &[u8], end: &[u8]) -> RangeEstimate {
+        SkipList::estimate_range(self, begin, end)
+    }
+
+    fn len(&self) -> usize {
+        SkipList::len(self)
+    }
+
+    fn memory_usage(&self) -> usize {
+        SkipList::memory_usage(self)
+    }
+}
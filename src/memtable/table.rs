@@ -1,16 +1,27 @@
 use crate::error::Result;
-use crate::format::{ValueTag, ValueType};
+use crate::format::{SequenceNumber, ValueTag, ValueType};
 use crate::logger::LogNumber;
+use crate::memtable::bloom_filter::BloomFilter;
 use crate::memtable::internal_key::{InternalKey, InternalKeyComparator};
-use crate::skip_list::{LevelGenerator, SkipList, SkipListIterator};
-use crate::Comparator;
+use crate::memtable::range_tombstone::{RangeTombstone, RangeTombstones};
+use crate::memtable::rep::{MemTableRep, MemTableRepIterator};
+use crate::skip_list::{LevelGenerator, RangeEstimate, SkipList};
+use crate::{Comparator, InplaceCallback, InplaceDecision, MemTableBloomFilter};
 use bytes::Bytes;
 use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::sync::RwLock;
 
 #[allow(dead_code)]
 pub struct MemTableMut<C: Comparator> {
     log_number: LogNumber,
-    skip_list: SkipList<InternalKeyComparator<C>>,
+    rep: Box<dyn MemTableRep>,
+    bloom_filter: Option<BloomFilter>,
+    range_tombstones: RangeTombstones<C>,
+    inplace_locks: Option<Box<[RwLock<()>]>>,
+    _key_comparator: PhantomData<C>,
 }
 
 #[allow(dead_code)]
@@ -20,25 +31,238 @@ impl<C: Comparator> MemTableMut<C> {
         level_generator: Box<dyn LevelGenerator>,
         block_size: usize,
     ) -> Self {
+        Self::with_rep(
+            log_number,
+            Box::new(SkipList::<InternalKeyComparator<C>>::new(
+                level_generator,
+                block_size,
+            )),
+        )
+    }
+
+    /// `rep` must order its keys with `InternalKeyComparator<C>`.
+    pub fn with_rep(log_number: LogNumber, rep: Box<dyn MemTableRep>) -> Self {
         MemTableMut {
             log_number,
-            skip_list: SkipList::new(level_generator, block_size),
+            rep,
+            bloom_filter: None,
+            range_tombstones: RangeTombstones::new(),
+            inplace_locks: None,
+            _key_comparator: PhantomData,
         }
     }
 
+    /// Must be set before the first key is added.
+    pub fn set_bloom_filter(&mut self, options: MemTableBloomFilter) {
+        assert!(self.is_empty());
+
+        self.bloom_filter = Some(BloomFilter::new(options.bits, options.prefix_length));
+    }
+
+    /// Enables `update`, the keys are spread over `num_locks` locks that the reads of `get` and
+    /// the updates of a key take. Until the memtable is frozen, its values are then only read by
+    /// `get`: the reads lending out the values without a lock panic.
+    pub fn set_inplace_update(&mut self, num_locks: usize) {
+        self.inplace_locks = Some((0..num_locks.max(1)).map(|_| RwLock::new(())).collect());
+    }
+
+    // the values read without a lock can be changed by an update while they are borrowed
+    fn assert_no_inplace_update(&self) {
+        assert!(
+            self.inplace_locks.is_none(),
+            "the values of a memtable updated in place are only read by `get`"
+        );
+    }
+
+    fn inplace_lock(&self, key: &[u8]) -> Option<&RwLock<()>> {
+        self.inplace_locks.as_ref().map(|locks| {
+            let mut hasher = DefaultHasher::new();
+            key.hash(&mut hasher);
+
+            &locks[hasher.finish() as usize % locks.len()]
+        })
+    }
+
     pub fn log_number(&self) -> LogNumber {
         self.log_number
     }
 
     pub fn add(&self, key: InternalKey, value: Bytes) {
-        self.skip_list.insert(key.into(), value);
+        if let Some(bloom_filter) = self.bloom_filter.as_ref() {
+            bloom_filter.add(key.key().as_ref());
+        }
+
+        self.rep.insert(key.into(), value);
+    }
+
+    /// `key` holds the beginning of the range and the sequence number of the deletion.
+    pub fn add_range_tombstone(&self, key: InternalKey, end: Bytes) {
+        self.range_tombstones.add(RangeTombstone {
+            begin: key.key().clone(),
+            end,
+            sequence: key.value_tag().sequence_number,
+        });
+    }
+
+    pub fn range_tombstones(&self) -> Vec<RangeTombstone> {
+        self.range_tombstones.all()
+    }
+
+    /// The sequence number of the newest range deletion of `key` not newer than `sequence`, the
+    /// versions older than it are deleted.
+    pub fn max_covering_tombstone(
+        &self,
+        key: &[u8],
+        sequence: SequenceNumber,
+    ) -> Option<SequenceNumber> {
+        self.range_tombstones.max_covering(key, sequence)
+    }
+
+    /// Adds like `add`, faster when the thread adds its keys in order.
+    pub fn add_with_hint(&self, key: InternalKey, value: Bytes) {
+        if let Some(bloom_filter) = self.bloom_filter.as_ref() {
+            bloom_filter.add(key.key().as_ref());
+        }
+
+        self.rep.insert_with_hint(key.into(), value);
+    }
+
+    /// Puts `value` by updating the newest version of the key in place, or adds it as a new
+    /// version when it does not fit or the version is not in this memtable. The updated version
+    /// keeps its sequence number, so the sessions older than the update see it too.
+    ///
+    /// The newest version is the newest one older than `key`, a version newer than `key` is
+    /// never updated by it. The writes of a key, tombstones included, must be made in the order
+    /// of their sequences for the last write to win.
+    ///
+    /// `callback` merges `value` into the newest version. For the keys without a version in this
+    /// memtable, the version it merges into is read from the older memtables by `previous`.
+    pub fn update<F>(
+        &self,
+        key: InternalKey,
+        value: Bytes,
+        callback: Option<&dyn InplaceCallback>,
+        previous: F,
+    ) -> Result<()>
+    where
+        F: FnOnce() -> Result<Option<Bytes>>,
+    {
+        let lock = self
+            .inplace_lock(key.key().as_ref())
+            .expect("in-place update is not enabled");
+        let _guard = lock.write().unwrap();
+
+        let sequence = key.value_tag().sequence_number;
+        let deleted_at = self.max_covering_tombstone(key.key().as_ref(), sequence);
+        let newest = self
+            .rep
+            .seek_less_or_equal(key.as_bytes().as_ref())
+            .filter(|(found, _)| {
+                C::compare(InternalKey::split_key(found), key.key().as_ref()) == Ordering::Equal
+            });
+        // a version deleted by a range deletion is not updated, it reads as deleted
+        let newest = match (newest, deleted_at) {
+            (Some((found, _)), Some(deleted_at))
+                if InternalKey::split_value_tag(found).sequence_number < deleted_at =>
+            {
+                None
+            }
+            (newest, _) => newest,
+        };
+
+        let mut decision = None;
+
+        if let Some((found, _)) =
+            newest.filter(|(found, _)| InternalKey::split_value_tag(found).is_value())
+        {
+            let found = found.to_vec();
+
+            self.rep.update_value(found.as_slice(), &mut |room, len| {
+                let result = match callback {
+                    // a put takes the whole room of the value, which a shorter put left
+                    None if value.len() <= room.len() => {
+                        room[..value.len()].copy_from_slice(value.as_ref());
+                        InplaceDecision::Updated(value.len())
+                    }
+                    _ => Self::decide(key.key(), Some(&mut room[..len]), &value, callback),
+                };
+                let len = match result {
+                    InplaceDecision::Updated(len) => Some(len),
+                    _ => None,
+                };
+                decision = Some(result);
+
+                len
+            });
+        }
+
+        // the representation cannot update its values, or there is no value to update
+        let decision = match decision {
+            Some(decision) => decision,
+            None => {
+                let mut existing = match newest {
+                    Some((found, value)) if InternalKey::split_value_tag(found).is_value() => {
+                        Some(value.to_vec())
+                    }
+                    Some(_) => None,
+                    None if callback.is_some() && deleted_at.is_none() => {
+                        previous()?.map(|value| value.to_vec())
+                    }
+                    None => None,
+                };
+
+                match Self::decide(key.key(), existing.as_deref_mut(), &value, callback) {
+                    InplaceDecision::Updated(len) => match existing {
+                        Some(mut existing) => {
+                            existing.truncate(len);
+                            InplaceDecision::Insert(Bytes::from(existing))
+                        }
+                        None => InplaceDecision::Skip,
+                    },
+                    others => others,
+                }
+            }
+        };
+
+        if let InplaceDecision::Insert(value) = decision {
+            self.add(key, value);
+        }
+
+        Ok(())
+    }
+
+    fn decide(
+        key: &Bytes,
+        existing: Option<&mut [u8]>,
+        value: &Bytes,
+        callback: Option<&dyn InplaceCallback>,
+    ) -> InplaceDecision {
+        match (callback, existing) {
+            (Some(callback), existing) => callback.update(key.as_ref(), existing, value.as_ref()),
+            (None, Some(existing)) if value.len() <= existing.len() => {
+                existing[..value.len()].copy_from_slice(value.as_ref());
+                InplaceDecision::Updated(value.len())
+            }
+            (None, _) => InplaceDecision::Insert(value.clone()),
+        }
+    }
+
+    /// Reads like `seek_by_key_and_sequence`, the value is copied before an update of the key can
+    /// change it.
+    pub fn get(&self, key: &Bytes, sequence: u64) -> Result<Option<(ValueTag, Bytes)>> {
+        let _guard = self
+            .inplace_lock(key.as_ref())
+            .map(|lock| lock.read().unwrap());
+
+        Ok(self
+            .find(key, sequence)?
+            .map(|(tag, value)| (tag, Bytes::copy_from_slice(value))))
     }
 
     pub fn seek_by_internal_key(&self, key: &InternalKey) -> Option<&[u8]> {
-        let mut visitor = self.skip_list.visitor();
-        visitor.seek(key.as_bytes().as_ref());
+        self.assert_no_inplace_update();
 
-        visitor.value()
+        self.rep.get(key.as_bytes().as_ref())
     }
 
     pub fn seek_by_key_and_sequence(
@@ -46,32 +270,90 @@ impl<C: Comparator> MemTableMut<C> {
         key: &Bytes,
         sequence: u64,
     ) -> Result<Option<(ValueTag, &[u8])>> {
-        let mut visitor = self.skip_list.visitor();
+        self.assert_no_inplace_update();
+
+        self.find(key, sequence)
+    }
+
+    fn find(&self, key: &Bytes, sequence: u64) -> Result<Option<(ValueTag, &[u8])>> {
+        if let Some(bloom_filter) = self.bloom_filter.as_ref() {
+            if !bloom_filter.may_contain(key.as_ref()) {
+                return Ok(None);
+            }
+        }
+
         let search_key = InternalKey::new(key.clone(), ValueTag::new(sequence, ValueType::Value)?);
 
-        visitor.seek_less_or_equal(search_key.as_bytes().as_ref());
+        Ok(
+            match self.rep.seek_less_or_equal(search_key.as_bytes().as_ref()) {
+                None => None,
+                Some((result_key, value)) => {
+                    let (tag, result_key) = InternalKey::split(result_key);
 
-        Ok(match visitor.key().map(|k| InternalKey::split(k)) {
-            None => None,
-            Some((tag, result_key)) => {
-                if C::compare(key.as_ref(), result_key) == Ordering::Equal {
-                    visitor.value().map(|v| (tag, v))
-                } else {
-                    None
+                    if C::compare(key.as_ref(), result_key) == Ordering::Equal {
+                        Some((tag, value))
+                    } else {
+                        None
+                    }
                 }
-            }
-        })
+            },
+        )
     }
 
     pub fn memory_usage(&self) -> usize {
-        self.skip_list.memory_usage()
+        self.rep.memory_usage()
+            + self.range_tombstones.memory_usage()
+            + self
+                .bloom_filter
+                .as_ref()
+                .map_or(0, |bloom_filter| bloom_filter.memory_usage())
+    }
+
+    /// Estimates the versions of the keys in `[begin, end)`, only the lengths of the values are
+    /// read so the in-place updates do not need to be kept out.
+    pub fn estimate_range(&self, begin: &Bytes, end: &Bytes) -> RangeEstimate {
+        // sequence 0 sorts before every other version of a key
+        let bound = |key: &Bytes| {
+            InternalKey::new(
+                key.clone(),
+                ValueTag {
+                    sequence_number: 0,
+                    ty: ValueType::Value,
+                },
+            )
+        };
+
+        self.rep.estimate_range(
+            bound(begin).as_bytes().as_ref(),
+            bound(end).as_bytes().as_ref(),
+        )
+    }
+
+    pub fn iter(&self) -> MemTableRepIterator<'_> {
+        self.assert_no_inplace_update();
+
+        self.rep.iter()
     }
 
-    pub fn iter(&self) -> SkipListIterator<InternalKeyComparator<C>> {
-        self.skip_list.iter()
+    pub fn iter_from(&self, key: &InternalKey) -> MemTableRepIterator<'_> {
+        self.assert_no_inplace_update();
+
+        self.rep.iter_from(key.as_bytes().as_ref())
+    }
+
+    pub fn len(&self) -> usize {
+        self.rep.len()
     }
 
-    pub fn freeze(self) -> MemTable<C> {
+    pub fn is_empty(&self) -> bool {
+        self.rep.is_empty() && self.range_tombstones.is_empty()
+    }
+
+    pub fn freeze(mut self) -> MemTable<C> {
+        self.rep.freeze();
+        // nothing is updated anymore, the values can be read without the locks
+        self.inplace_locks = None;
+
         self.into()
     }
 }
@@ -105,8 +387,40 @@ impl<C: Comparator> MemTable<C> {
         self.memtable.seek_by_key_and_sequence(key, sequence)
     }
 
-    pub fn iter(&self) -> SkipListIterator<InternalKeyComparator<C>> {
+    pub fn range_tombstones(&self) -> Vec<RangeTombstone> {
+        self.memtable.range_tombstones()
+    }
+
+    pub fn max_covering_tombstone(
+        &self,
+        key: &[u8],
+        sequence: SequenceNumber,
+    ) -> Option<SequenceNumber> {
+        self.memtable.max_covering_tombstone(key, sequence)
+    }
+
+    pub fn estimate_range(&self, begin: &Bytes, end: &Bytes) -> RangeEstimate {
+        self.memtable.estimate_range(begin, end)
+    }
+
+    pub fn iter(&self) -> MemTableRepIterator<'_> {
         self.memtable.iter()
     }
+
+    pub fn iter_from(&self, key: &InternalKey) -> MemTableRepIterator<'_> {
+        self.memtable.iter_from(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.memtable.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.memtable.is_empty()
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.memtable.memory_usage()
+    }
 }
 unsafe impl<C: Comparator> Sync for MemTable<C> {}
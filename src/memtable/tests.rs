@@ -1,12 +1,20 @@
+use crate::memtable::bloom_filter::BloomFilter;
 use crate::memtable::internal_key::{InternalKey, InternalKeyComparator};
+use crate::memtable::rep::MemTableRep;
 use crate::memtable::table::MemTableMut;
-use crate::skip_list::RandomLevelGenerator;
-use crate::{Comparator, NumberComparator};
+use crate::memtable::vector_rep::VectorRep;
+use crate::memtable::{new_rep, SkipListSettings};
+use crate::skip_list::{max_level_for_entries, RandomLevelGenerator};
+use crate::{
+    Comparator, InplaceCallback, InplaceDecision, LevelGeneratorType, MemTableBloomFilter,
+    MemTableRepType, NumberComparator, Options,
+};
 
 use crate::format::{ValueTag, ValueType};
 use crate::helper::test::*;
 use bytes::Bytes;
 use std::cmp::Ordering;
+use std::convert::TryInto;
 use std::sync::Arc;
 
 #[test]
@@ -102,3 +110,364 @@ fn test_concurrent() {
 
     pool.join();
 }
+
+#[test]
+fn test_memtable_rep() {
+    for rep_type in [
+        MemTableRepType::SkipList,
+        MemTableRepType::HashPrefix {
+            prefix_length: 2,
+            bucket_count: 16,
+        },
+        MemTableRepType::Vector,
+    ] {
+        let memtable = MemTableMut::<NumberComparator<u32>>::with_rep(
+            0,
+            new_rep::<InternalKeyComparator<NumberComparator<u32>>>(
+                rep_type,
+                SkipListSettings::default(),
+            ),
+        );
+
+        for key in (0..1000).rev() {
+            for sequence in [1, 3] {
+                memtable.add(
+                    InternalKey::new(
+                        get_bytes(key),
+                        ValueTag::new(sequence, ValueType::Value).unwrap(),
+                    ),
+                    get_bytes(key + sequence as u32),
+                );
+            }
+        }
+
+        // the first insert of a key wins
+        memtable.add(
+            InternalKey::new(get_bytes(10), ValueTag::new(1, ValueType::Value).unwrap()),
+            get_bytes(0),
+        );
+
+        let check = |memtable: &dyn Fn(u32, u64) -> Option<Bytes>| {
+            for key in 0..1000 {
+                assert_eq!(memtable(key, 0), None);
+                assert_eq!(memtable(key, 2), Some(get_bytes(key + 1)));
+                assert_eq!(memtable(key, 5), Some(get_bytes(key + 3)));
+            }
+            assert_eq!(memtable(1000, 5), None);
+        };
+
+        check(&|key, sequence| {
+            memtable
+                .seek_by_key_and_sequence(&get_bytes(key), sequence)
+                .unwrap()
+                .map(|(_, value)| Bytes::copy_from_slice(value))
+        });
+        let memtable = memtable.freeze();
+        assert_eq!(memtable.len(), 2000);
+
+        check(&|key, sequence| {
+            memtable
+                .seek_by_key_and_sequence(&get_bytes(key), sequence)
+                .unwrap()
+                .map(|(_, value)| Bytes::copy_from_slice(value))
+        });
+        assert_eq!(
+            memtable
+                .seek_by_internal_key(&InternalKey::new(
+                    get_bytes(7),
+                    ValueTag::new(3, ValueType::Value).unwrap()
+                ))
+                .map(get_u32),
+            Some(10)
+        );
+
+        let keys = memtable
+            .iter()
+            .map(|(key, _)| {
+                let (tag, key) = InternalKey::split(key);
+                (get_u32(key), tag.sequence_number)
+            })
+            .collect::<Vec<_>>();
+        let expected = (0..1000)
+            .flat_map(|key| vec![(key, 1), (key, 3)])
+            .collect::<Vec<_>>();
+        assert_eq!(keys, expected);
+
+        let from = InternalKey::new(get_bytes(500), ValueTag::new(2, ValueType::Value).unwrap());
+        assert_eq!(
+            memtable
+                .iter_from(&from)
+                .map(|(key, _)| get_u32(InternalKey::split_key(key)))
+                .next(),
+            Some(500)
+        );
+        assert_eq!(memtable.iter_from(&from).count(), 999);
+    }
+}
+
+#[test]
+fn test_bloom_filter() {
+    let bloom_filter = BloomFilter::new(10 * 1000, None);
+
+    for key in 0..1000 {
+        bloom_filter.add(get_bytes(key).as_ref());
+    }
+
+    for key in 0..1000 {
+        assert!(bloom_filter.may_contain(get_bytes(key).as_ref()));
+    }
+
+    let false_positives = (1000..11000)
+        .filter(|key| bloom_filter.may_contain(get_bytes(*key).as_ref()))
+        .count();
+    assert!(false_positives < 300);
+
+    // the keys sharing the first two bytes of an added key
+    let prefix_filter = BloomFilter::new(1024, Some(2));
+    prefix_filter.add(&[1, 2, 3]);
+    assert!(prefix_filter.may_contain(&[1, 2, 4, 5]));
+    assert!(prefix_filter.may_contain(&[1, 2]));
+
+    let mut memtable = MemTableMut::<NumberComparator<u32>>::new(
+        0,
+        Box::new(RandomLevelGenerator::new(10, 0.1)),
+        4 * 1024,
+    );
+    memtable.set_bloom_filter(MemTableBloomFilter {
+        bits: 10 * 1000,
+        prefix_length: None,
+    });
+
+    for key in (0..2000).step_by(2) {
+        memtable.add(
+            InternalKey::new(get_bytes(key), ValueTag::new(1, ValueType::Value).unwrap()),
+            get_bytes(key),
+        );
+    }
+
+    for key in 0..2000 {
+        let result = memtable
+            .seek_by_key_and_sequence(&get_bytes(key), 1)
+            .unwrap()
+            .map(|(_, value)| get_u32(value));
+        assert_eq!(result, if key % 2 == 0 { Some(key) } else { None });
+    }
+}
+
+#[test]
+fn test_inplace_update() {
+    let mut memtable = MemTableMut::<NumberComparator<u32>>::new(
+        0,
+        Box::new(RandomLevelGenerator::new(10, 0.1)),
+        4 * 1024,
+    );
+    memtable.set_inplace_update(16);
+
+    let put = |sequence: u64, value: &[u8]| {
+        memtable
+            .update(
+                InternalKey::new(
+                    get_bytes(1),
+                    ValueTag::new(sequence, ValueType::Value).unwrap(),
+                ),
+                Bytes::copy_from_slice(value),
+                None,
+                || unreachable!(),
+            )
+            .unwrap();
+    };
+
+    for sequence in 0..1000 {
+        put(sequence, &(sequence as u32).to_le_bytes());
+    }
+
+    assert_eq!(memtable.len(), 1);
+    let (tag, value) = memtable.get(&get_bytes(1), 1000).unwrap().unwrap();
+    assert_eq!(tag.sequence_number, 0);
+    assert_eq!(value.as_ref(), 999_u32.to_le_bytes());
+
+    // a shorter value fits
+    put(1000, &[7]);
+    assert_eq!(memtable.len(), 1);
+    assert_eq!(
+        memtable
+            .get(&get_bytes(1), 1000)
+            .unwrap()
+            .unwrap()
+            .1
+            .as_ref(),
+        [7]
+    );
+
+    // the value grows back up to the room it was first written to
+    put(1001, &[1, 2, 3, 4]);
+    assert_eq!(memtable.len(), 1);
+    assert_eq!(
+        memtable
+            .get(&get_bytes(1), 1001)
+            .unwrap()
+            .unwrap()
+            .1
+            .as_ref(),
+        [1, 2, 3, 4]
+    );
+
+    put(1002, &[1, 2, 3, 4, 5]);
+    assert_eq!(memtable.len(), 2);
+    assert_eq!(
+        memtable
+            .get(&get_bytes(1), 1002)
+            .unwrap()
+            .unwrap()
+            .1
+            .as_ref(),
+        [1, 2, 3, 4, 5]
+    );
+}
+
+#[test]
+fn test_inplace_update_order() {
+    struct Counter;
+
+    impl InplaceCallback for Counter {
+        fn name(&self) -> &str {
+            "counter"
+        }
+
+        fn update(&self, _: &[u8], existing: Option<&mut [u8]>, value: &[u8]) -> InplaceDecision {
+            let delta = u64::from_le_bytes(value.try_into().unwrap());
+
+            match existing {
+                Some(existing) => {
+                    let count = u64::from_le_bytes((&*existing).try_into().unwrap());
+                    existing.copy_from_slice(&(count + delta).to_le_bytes());
+                    InplaceDecision::Updated(8)
+                }
+                None => InplaceDecision::Insert(Bytes::copy_from_slice(value)),
+            }
+        }
+    }
+
+    let mut memtable = MemTableMut::<NumberComparator<u32>>::new(
+        0,
+        Box::new(RandomLevelGenerator::new(10, 0.1)),
+        4 * 1024,
+    );
+    memtable.set_inplace_update(16);
+
+    let put = |sequence: u64| {
+        memtable
+            .update(
+                InternalKey::new(
+                    get_bytes(1),
+                    ValueTag::new(sequence, ValueType::Value).unwrap(),
+                ),
+                Bytes::copy_from_slice(&1_u64.to_le_bytes()),
+                Some(&Counter),
+                || Ok(None),
+            )
+            .unwrap();
+    };
+
+    // a write arriving after a newer one leaves it alone, the database makes the writes of a
+    // key in the order of their sequences
+    put(30);
+    put(21);
+    assert_eq!(memtable.len(), 2);
+    assert_eq!(
+        memtable.get(&get_bytes(1), 31).unwrap().unwrap().1.as_ref(),
+        1_u64.to_le_bytes()
+    );
+
+    // the updates in order merge into the newest version
+    for sequence in 31..=40 {
+        put(sequence);
+    }
+    assert_eq!(memtable.len(), 2);
+    let (tag, value) = memtable.get(&get_bytes(1), 41).unwrap().unwrap();
+    assert_eq!(tag.sequence_number, 30);
+    assert_eq!(value.as_ref(), 11_u64.to_le_bytes());
+}
+
+#[test]
+#[should_panic(expected = "only read by `get`")]
+fn test_inplace_update_iter() {
+    let mut memtable = MemTableMut::<NumberComparator<u32>>::new(
+        0,
+        Box::new(RandomLevelGenerator::new(10, 0.1)),
+        4 * 1024,
+    );
+    memtable.set_inplace_update(16);
+
+    // the values are lent out without the locks
+    memtable.iter().count();
+}
+
+#[test]
+fn test_vector_rep_interleaved_reads() {
+    let rep = VectorRep::<NumberComparator<u32>>::new();
+
+    // every read after an insert sorts the entries again, and sees the insert
+    for key in (0..200).rev() {
+        rep.insert(get_bytes(key), get_bytes(key + 1));
+
+        assert_eq!(rep.get(&get_bytes(key)).map(get_u32), Some(key + 1));
+        assert_eq!(rep.iter().count(), 200 - key as usize);
+    }
+
+    let keys = rep.iter().map(|(key, _)| get_u32(key)).collect::<Vec<_>>();
+    assert_eq!(keys, (0..200).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_skip_list_settings() {
+    let mut options = Options::new(Default::default());
+    options.memtable_level_generator = LevelGeneratorType::Seeded { seed: 7 };
+    options.memtable_expected_entries = Some(1_000_000);
+    options.arena_block_size = 64 * 1024;
+
+    let settings = SkipListSettings::from_options(&options);
+    assert_eq!(settings.max_level, max_level_for_entries(1_000_000, 0.1));
+    assert_eq!(settings.block_size, 64 * 1024);
+
+    // the skip lists of the same settings draw the same levels
+    let levels = || {
+        let generator = settings.level_generator();
+        assert_eq!(generator.max_level(), settings.max_level);
+
+        (0..1000)
+            .map(|_| generator.generate_level())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(levels(), levels());
+
+    for level_generator in [
+        LevelGeneratorType::Random,
+        LevelGeneratorType::Seeded { seed: 7 },
+        LevelGeneratorType::XorShift,
+    ] {
+        let memtable = MemTableMut::<NumberComparator<u32>>::with_rep(
+            0,
+            new_rep::<InternalKeyComparator<NumberComparator<u32>>>(
+                MemTableRepType::SkipList,
+                SkipListSettings {
+                    level_generator,
+                    ..settings
+                },
+            ),
+        );
+
+        for key in 0..1000 {
+            memtable.add(
+                InternalKey::new(get_bytes(key), ValueTag::new(1, ValueType::Value).unwrap()),
+                get_bytes(key),
+            );
+        }
+
+        let keys = memtable
+            .iter()
+            .map(|(key, _)| get_u32(InternalKey::split_key(key)))
+            .collect::<Vec<_>>();
+        assert_eq!(keys, (0..1000).collect::<Vec<_>>());
+    }
+}
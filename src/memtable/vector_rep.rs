@@ -0,0 +1,196 @@
+use crate::memtable::rep::{MemTableRep, MemTableRepIterator};
+use crate::skip_list::RangeEstimate;
+use crate::Comparator;
+use bytes::Bytes;
+use std::cmp::Ordering;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::{Arc, OnceLock, RwLock};
+
+// the first segment holds this many entries, every next one twice as many as the previous one
+const FIRST_SEGMENT_SIZE: usize = 64;
+const SEGMENT_COUNT: usize = 48;
+
+type Segment = Box<[OnceLock<(Bytes, Bytes)>]>;
+
+// the indexes of the entries in key order, taken when `len` entries were inserted
+#[derive(Default)]
+struct SortedIndexes {
+    len: usize,
+    indexes: Arc<Vec<usize>>,
+}
+
+/// Appends the entries to a vector that is sorted once when the memtable is frozen, which makes
+/// bulk loads cheap. The reads of an unfrozen memtable sort the indexes of the entries again
+/// after every insert, the sorted indexes are reused until the next one.
+pub struct VectorRep<K: Comparator> {
+    // an entry is never moved once appended, so the reads can lend it out
+    segments: Box<[OnceLock<Segment>]>,
+    // the slots handed out to the inserts, and the inserts completed
+    reserved: AtomicUsize,
+    len: AtomicUsize,
+    sorted: RwLock<SortedIndexes>,
+    frozen: bool,
+    memory_usage: AtomicUsize,
+    _key_comparator: PhantomData<K>,
+}
+
+unsafe impl<K: Comparator> Send for VectorRep<K> {}
+unsafe impl<K: Comparator> Sync for VectorRep<K> {}
+
+#[allow(dead_code)]
+impl<K: Comparator> VectorRep<K> {
+    pub fn new() -> Self {
+        VectorRep {
+            segments: (0..SEGMENT_COUNT).map(|_| OnceLock::new()).collect(),
+            reserved: AtomicUsize::new(0),
+            len: AtomicUsize::new(0),
+            sorted: RwLock::new(SortedIndexes::default()),
+            frozen: false,
+            memory_usage: AtomicUsize::new(0),
+            _key_comparator: PhantomData,
+        }
+    }
+
+    // the segment of the entry at `index` and its position in the segment
+    fn locate(index: usize) -> (usize, usize) {
+        let segment = (usize::BITS - 1 - (index / FIRST_SEGMENT_SIZE + 1).leading_zeros()) as usize;
+
+        (segment, index - FIRST_SEGMENT_SIZE * ((1 << segment) - 1))
+    }
+
+    fn slot(&self, index: usize) -> &OnceLock<(Bytes, Bytes)> {
+        let (segment, offset) = Self::locate(index);
+
+        &self.segments[segment].get_or_init(|| {
+            (0..FIRST_SEGMENT_SIZE << segment)
+                .map(|_| OnceLock::new())
+                .collect()
+        })[offset]
+    }
+
+    fn entry(&self, index: usize) -> (&[u8], &[u8]) {
+        let (key, value) = self.slot(index).get().unwrap();
+
+        (key.as_ref(), value.as_ref())
+    }
+
+    // the stable sort keeps the first insert of a duplicated key
+    fn sort(&self) -> SortedIndexes {
+        let len = self.len.load(AtomicOrdering::SeqCst);
+        // the inserts still writing their entries are left out
+        let mut indexes = (0..self.reserved.load(AtomicOrdering::SeqCst))
+            .filter(|index| self.slot(*index).get().is_some())
+            .collect::<Vec<_>>();
+        indexes.sort_by(|a, b| K::compare(self.entry(*a).0, self.entry(*b).0));
+        indexes.dedup_by(|a, b| K::compare(self.entry(*a).0, self.entry(*b).0) == Ordering::Equal);
+
+        SortedIndexes {
+            len,
+            indexes: Arc::new(indexes),
+        }
+    }
+
+    fn sorted_indexes(&self) -> Arc<Vec<usize>> {
+        let len = self.len.load(AtomicOrdering::SeqCst);
+        {
+            let sorted = self.sorted.read().unwrap();
+
+            if sorted.len == len {
+                return sorted.indexes.clone();
+            }
+        }
+
+        let mut sorted = self.sorted.write().unwrap();
+
+        if sorted.len != self.len.load(AtomicOrdering::SeqCst) {
+            *sorted = self.sort();
+        }
+
+        sorted.indexes.clone()
+    }
+
+    // the position in `indexes` of the first entry whose key is greater than or equal to `key`
+    fn lower_bound(&self, indexes: &[usize], key: &[u8]) -> usize {
+        indexes.partition_point(|index| K::compare(self.entry(*index).0, key) == Ordering::Less)
+    }
+}
+
+impl<K: Comparator> MemTableRep for VectorRep<K> {
+    fn insert(&self, key: Bytes, value: Bytes) {
+        assert!(!self.frozen);
+
+        self.memory_usage
+            .fetch_add(key.len() + value.len(), AtomicOrdering::Relaxed);
+        let index = self.reserved.fetch_add(1, AtomicOrdering::SeqCst);
+        let _ = self.slot(index).set((key, value));
+        self.len.fetch_add(1, AtomicOrdering::SeqCst);
+    }
+
+    fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        let indexes = self.sorted_indexes();
+
+        indexes
+            .get(self.lower_bound(indexes.as_slice(), key))
+            .map(|index| self.entry(*index))
+            .filter(|(entry, _)| K::compare(entry, key) == Ordering::Equal)
+            .map(|(_, value)| value)
+    }
+
+    fn seek_less_or_equal(&self, key: &[u8]) -> Option<(&[u8], &[u8])> {
+        let indexes = self.sorted_indexes();
+        let end = indexes
+            .partition_point(|index| K::compare(self.entry(*index).0, key) != Ordering::Greater);
+
+        end.checked_sub(1)
+            .map(|position| self.entry(indexes[position]))
+    }
+
+    fn iter(&self) -> MemTableRepIterator<'_> {
+        let indexes = self.sorted_indexes();
+
+        Box::new((0..indexes.len()).map(move |position| self.entry(indexes[position])))
+    }
+
+    fn iter_from(&self, key: &[u8]) -> MemTableRepIterator<'_> {
+        let indexes = self.sorted_indexes();
+        let start = self.lower_bound(indexes.as_slice(), key);
+
+        Box::new((start..indexes.len()).map(move |position| self.entry(indexes[position])))
+    }
+
+    fn estimate_range(&self, begin: &[u8], end: &[u8]) -> RangeEstimate {
+        let indexes = self.sorted_indexes();
+        let start = self.lower_bound(indexes.as_slice(), begin);
+        let end = self.lower_bound(indexes.as_slice(), end).max(start);
+
+        RangeEstimate {
+            count: end - start,
+            size: indexes[start..end]
+                .iter()
+                .map(|index| {
+                    let (key, value) = self.entry(*index);
+                    key.len() + value.len()
+                })
+                .sum(),
+        }
+    }
+
+    // the duplicated keys are counted until the memtable is frozen
+    fn len(&self) -> usize {
+        if self.frozen {
+            self.sorted.read().unwrap().indexes.len()
+        } else {
+            self.len.load(AtomicOrdering::SeqCst)
+        }
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.memory_usage.load(AtomicOrdering::Relaxed)
+    }
+
+    fn freeze(&mut self) {
+        self.sorted_indexes();
+        self.frozen = true;
+    }
+}
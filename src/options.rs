@@ -0,0 +1,185 @@
+use crate::error::{Error, Result};
+use crate::{CompactionFilter, CompressionType, InplaceCallback, RateLimiter, WriteBufferManager};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub struct Options {
+    pub dir: PathBuf,
+    pub log_block_size: usize,
+    pub compaction_filter: Option<Arc<dyn CompactionFilter>>,
+    pub max_subcompactions: usize,
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+    pub wal_sync_mode: WalSyncMode,
+    pub wal_recovery_mode: WalRecoveryMode,
+    /// Number of obsolete log files kept to be reused by the next logs instead of being deleted,
+    /// which saves the allocation of a new file on every rotation. Every chunk holds the number
+    /// of its log, so a reused log ends at the first chunk of the previous one.
+    pub recycle_log_file_num: usize,
+    /// Disk space reserved for every new log file.
+    pub log_preallocate_size: Option<usize>,
+    /// Compression of the values written to the logs. A value is stored uncompressed when the
+    /// compression does not make it smaller.
+    pub wal_compression: CompressionType,
+    /// The obsolete logs are moved into the `archive` directory instead of being deleted when
+    /// either limit is set, so `KomachiDB::get_updates_since` can still read them. Archived logs
+    /// are deleted once older than `wal_ttl`, then the oldest ones while the archive is larger
+    /// than `wal_size_limit` bytes.
+    pub wal_ttl: Option<Duration>,
+    pub wal_size_limit: Option<u64>,
+    /// Buffers the log writes in memory until `KomachiDB::flush_wal` is called. The buffered
+    /// writes are lost by a crash, and reported by `RecoveryReport::unlogged_writes_lost`.
+    pub manual_wal_flush: bool,
+    /// The structure holding the entries of the memtables.
+    pub memtable_rep: MemTableRepType,
+    /// How the skip lists of the memtables draw the levels of their nodes.
+    pub memtable_level_generator: LevelGeneratorType,
+    /// The number of entries a skip list of the memtables is expected to hold, its max level is
+    /// chosen to suit them. `None` keeps the default max level.
+    pub memtable_expected_entries: Option<usize>,
+    /// The size of the blocks the skip lists of the memtables allocate their nodes from.
+    pub arena_block_size: usize,
+    /// Starts the search for the position of a write in the memtable from the position of the
+    /// previous write of the same thread, which is faster when each thread writes its keys in
+    /// order and slower otherwise.
+    pub memtable_insert_with_hint: bool,
+    /// A bloom filter kept by every memtable, the reads of the keys it does not contain skip the
+    /// memtable.
+    pub memtable_bloom_filter: Option<MemTableBloomFilter>,
+    /// A put updates the value of the newest version of its key in the mutable memtable when the
+    /// new value is not longer than the one the version was first written with, instead of adding
+    /// a new version. The sessions then see the
+    /// updates made after they were created.
+    pub inplace_update_support: bool,
+    /// The number of locks the keys are spread over for the in-place updates.
+    pub inplace_update_num_locks: usize,
+    /// Merges the value of a put into the newest version of the key when
+    /// `inplace_update_support` is set.
+    pub inplace_callback: Option<Arc<dyn InplaceCallback>>,
+    /// Charges the memtables to a memory budget, which can be shared by several databases.
+    pub write_buffer_manager: Option<Arc<WriteBufferManager>>,
+}
+
+impl Options {
+    pub fn new(dir: PathBuf) -> Self {
+        Options {
+            dir,
+            log_block_size: 32 * 1024,
+            compaction_filter: None,
+            max_subcompactions: 1,
+            rate_limiter: None,
+            wal_sync_mode: WalSyncMode::NoSync,
+            wal_recovery_mode: WalRecoveryMode::TolerateCorruptedTailRecords,
+            recycle_log_file_num: 0,
+            log_preallocate_size: None,
+            wal_compression: CompressionType::NoCompression,
+            wal_ttl: None,
+            wal_size_limit: None,
+            manual_wal_flush: false,
+            memtable_rep: MemTableRepType::SkipList,
+            memtable_level_generator: LevelGeneratorType::Random,
+            memtable_expected_entries: None,
+            arena_block_size: 4 * 1024,
+            memtable_insert_with_hint: false,
+            memtable_bloom_filter: None,
+            inplace_update_support: false,
+            inplace_update_num_locks: 10000,
+            inplace_callback: None,
+            write_buffer_manager: None,
+        }
+    }
+
+    /// Rejects the combinations of options the database cannot work with.
+    pub fn validate(&self) -> Result<()> {
+        // every in-place update reads the unfrozen memtable, which sorts a vector again
+        if self.inplace_update_support && self.memtable_rep == MemTableRepType::Vector {
+            return Err(Error::InvalidOptions(
+                "inplace_update_support cannot be used with MemTableRepType::Vector".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// How the write-ahead log reaches the disk. Whatever the mode, a logged write is handed to the
+/// OS before it returns, so it survives a crash of the process. The exceptions are the writes
+/// buffered by `Options::manual_wal_flush` until `KomachiDB::flush_wal`, and the writes with
+/// `WriteOptions::disable_wal`, which are not logged at all.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum WalSyncMode {
+    /// Every write is synced before it returns and survives a crash of the machine.
+    EveryWrite,
+    /// A background thread syncs the log at the given interval, so a crash of the machine loses
+    /// at most the writes of the last interval.
+    Periodic(Duration),
+    /// The log is synced only by writes with `WriteOptions::sync`, the OS decides when the other
+    /// writes reach the disk.
+    NoSync,
+}
+
+/// How the logs are replayed when a corrupted or incomplete record is found.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum WalRecoveryMode {
+    /// An incomplete or corrupted record at the end of a log, left by a crash during a write, is
+    /// dropped. Any other corruption fails the recovery.
+    TolerateCorruptedTailRecords,
+    /// Every corruption and incomplete record fails the recovery.
+    AbsoluteConsistency,
+    /// The replay stops at the first corruption, the records after it and all later logs are
+    /// dropped, so the database is restored to a consistent point in time. The later logs are
+    /// moved to the `lost` directory and listed by `RecoveryReport::lost_logs`.
+    PointInTimeRecovery,
+    /// Corrupted records are dropped and the replay continues after them.
+    SkipAnyCorruptedRecords,
+}
+
+/// The structure holding the entries of a memtable.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MemTableRepType {
+    /// A concurrent skip list, suits every workload.
+    SkipList,
+    /// Spreads the entries over `bucket_count` skip lists by the first `prefix_length` bytes of
+    /// their keys. The reads of a key are faster, while every iteration over the memtable has to
+    /// sort all its entries.
+    HashPrefix {
+        prefix_length: usize,
+        bucket_count: usize,
+    },
+    /// Appends the writes to a vector sorted when the memtable is frozen, suits bulk loads that
+    /// do not read the data they write. Cannot be used with `inplace_update_support`.
+    Vector,
+}
+
+/// How a skip list draws the levels of its nodes.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum LevelGeneratorType {
+    /// Draws from the generator of `rand` kept per thread.
+    Random,
+    /// Draws from a generator seeded with `seed`, the same writes give skip lists of the same
+    /// shape. The threads writing concurrently share the generator.
+    Seeded { seed: u64 },
+    /// Draws from a xorshift generator kept per thread, the cheapest draw.
+    XorShift,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct MemTableBloomFilter {
+    /// The size of the filter of each memtable, about ten bits per key keeps the false
+    /// positives around one percent.
+    pub bits: usize,
+    /// Filters on the first `prefix_length` bytes of the keys instead of the whole keys, the
+    /// keys sharing a prefix are then all read from the memtable.
+    pub prefix_length: Option<usize>,
+}
+
+#[derive(Default)]
+pub struct WriteOptions {
+    /// Sync the log before the write returns, whatever the `WalSyncMode` is.
+    pub sync: bool,
+    /// Skip the log, the write is lost when the database is closed or crashes.
+    pub disable_wal: bool,
+    /// Fail with `Error::WriteStall` instead of waiting when the write buffer manager stalls the
+    /// writes.
+    pub no_slowdown: bool,
+}
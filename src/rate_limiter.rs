@@ -0,0 +1,179 @@
+use std::cmp::{max, min};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+const REFILL_PERIOD: Duration = Duration::from_millis(100);
+
+/// Requests of a higher priority are always granted before waiting requests of a lower one.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum IOPriority {
+    Wal = 0,
+    Flush = 1,
+    Compaction = 2,
+}
+
+struct AutoTune {
+    min_bytes_per_second: u64,
+    max_bytes_per_second: u64,
+    pending_bytes_limit: u64,
+}
+
+struct RateLimiterState {
+    available: u64,
+    last_refill: Instant,
+    waiting: [usize; 3],
+}
+
+/// A token bucket shared by every background and foreground writer.
+pub struct RateLimiter {
+    bytes_per_second: AtomicU64,
+    auto_tune: Option<AutoTune>,
+    state: Mutex<RateLimiterState>,
+    condvar: Condvar,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_second: u64) -> Self {
+        RateLimiter {
+            bytes_per_second: AtomicU64::new(max(bytes_per_second, 1)),
+            auto_tune: None,
+            state: Mutex::new(RateLimiterState {
+                available: 0,
+                last_refill: Instant::now(),
+                waiting: [0; 3],
+            }),
+            condvar: Condvar::new(),
+        }
+    }
+
+    /// The rate follows the pending work reported by `tune`, from `min_bytes_per_second` when
+    /// nothing is pending up to `max_bytes_per_second` when `pending_bytes_limit` is reached.
+    pub fn auto_tuned(
+        min_bytes_per_second: u64,
+        max_bytes_per_second: u64,
+        pending_bytes_limit: u64,
+    ) -> Self {
+        let mut rate_limiter = Self::new(min_bytes_per_second);
+        rate_limiter.auto_tune = Some(AutoTune {
+            min_bytes_per_second: max(min_bytes_per_second, 1),
+            max_bytes_per_second: max(max_bytes_per_second, min_bytes_per_second),
+            pending_bytes_limit: max(pending_bytes_limit, 1),
+        });
+
+        rate_limiter
+    }
+
+    pub fn bytes_per_second(&self) -> u64 {
+        self.bytes_per_second.load(Ordering::SeqCst)
+    }
+
+    pub fn set_bytes_per_second(&self, bytes_per_second: u64) {
+        self.bytes_per_second
+            .store(max(bytes_per_second, 1), Ordering::SeqCst);
+        self.condvar.notify_all();
+    }
+
+    /// Reports the amount of pending background work, only used by auto-tuned limiters.
+    pub fn tune(&self, pending_bytes: u64) {
+        if let Some(auto_tune) = self.auto_tune.as_ref() {
+            let range = auto_tune.max_bytes_per_second - auto_tune.min_bytes_per_second;
+            let ratio = min(pending_bytes, auto_tune.pending_bytes_limit) as f64
+                / auto_tune.pending_bytes_limit as f64;
+
+            self.set_bytes_per_second(
+                auto_tune.min_bytes_per_second + (range as f64 * ratio) as u64,
+            );
+        }
+    }
+
+    /// Blocks until `bytes` may be written.
+    pub fn request(&self, bytes: usize, priority: IOPriority) {
+        let mut remaining = bytes as u64;
+
+        while remaining > 0 {
+            remaining -= self.acquire(remaining, priority);
+        }
+    }
+
+    fn burst_bytes(&self) -> u64 {
+        max(
+            self.bytes_per_second() * REFILL_PERIOD.as_millis() as u64 / 1000,
+            1,
+        )
+    }
+
+    // grants up to a burst of `bytes`, the burst is taken under the lock as the rate can change
+    // while waiting
+    fn acquire(&self, bytes: u64, priority: IOPriority) -> u64 {
+        let index = priority as usize;
+        let mut state = self.state.lock().unwrap();
+        state.waiting[index] += 1;
+
+        loop {
+            let now = Instant::now();
+            let elapsed = now.duration_since(state.last_refill);
+            let refilled =
+                (self.bytes_per_second() as u128 * elapsed.as_micros() / 1_000_000) as u64;
+
+            if refilled > 0 {
+                state.available = min(state.available + refilled, self.burst_bytes());
+                state.last_refill = now;
+            }
+
+            let preceded = state.waiting[..index].iter().any(|waiting| *waiting > 0);
+            let granted = min(bytes, self.burst_bytes());
+
+            if !preceded && state.available >= granted {
+                state.available -= granted;
+                state.waiting[index] -= 1;
+                self.condvar.notify_all();
+
+                break granted;
+            }
+
+            state = self.condvar.wait_timeout(state, REFILL_PERIOD).unwrap().0;
+        }
+    }
+}
+
+#[test]
+fn test_rate_limit() {
+    let rate_limiter = RateLimiter::new(1024 * 1024);
+    let start = Instant::now();
+
+    rate_limiter.request(512 * 1024, IOPriority::Compaction);
+
+    assert!(start.elapsed() >= Duration::from_millis(400));
+}
+
+#[test]
+fn test_auto_tune() {
+    let rate_limiter = RateLimiter::auto_tuned(1024, 8 * 1024, 1024 * 1024);
+
+    rate_limiter.tune(0);
+    assert_eq!(rate_limiter.bytes_per_second(), 1024);
+    rate_limiter.tune(512 * 1024);
+    assert_eq!(rate_limiter.bytes_per_second(), 4608);
+    rate_limiter.tune(4 * 1024 * 1024);
+    assert_eq!(rate_limiter.bytes_per_second(), 8 * 1024);
+}
+
+#[test]
+fn test_lower_rate_while_waiting() {
+    use std::sync::Arc;
+    use std::thread;
+
+    let rate_limiter = Arc::new(RateLimiter::new(1024 * 1024));
+
+    let writer = {
+        let rate_limiter = rate_limiter.clone();
+        thread::spawn(move || rate_limiter.request(256 * 1024, IOPriority::Wal))
+    };
+
+    // the burst the writer waits for is now larger than the bucket can hold
+    thread::sleep(Duration::from_millis(50));
+    rate_limiter.set_bytes_per_second(512 * 1024);
+
+    writer.join().unwrap();
+}
@@ -1,43 +1,230 @@
 use crate::core::DBCore;
+use crate::error::{Error, Result};
 use crate::session::session_handler::Session;
 use crate::Comparator;
-use std::collections::HashSet;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::{Arc, RwLock};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::thread::panicking;
+use std::time::Duration;
 
 pub struct SessionFactory {
+    // the sequence number of the next write
     sequence: AtomicU64,
-    in_use_sequence: RwLock<HashSet<u64>>,
+    // every write below it is in the memtable, the reads only see them
+    published: AtomicU64,
+    // the ranges of sequence numbers written after a range still being written
+    completed: Mutex<BTreeMap<u64, u64>>,
+    // notified under `completed` when `published` moves or the writes are stopped
+    published_signal: Condvar,
+    // set when a write failed after reaching the memtable, nothing is published past it
+    stopped: AtomicBool,
+    in_use_sequence: RwLock<HashMap<u64, usize>>,
 }
 
 unsafe impl Sync for SessionFactory {}
 
+/// Sequence numbers given to a write. They are published by `publish`, or when the range is
+/// dropped for a write that failed before reaching the memtable, once every write with a
+/// smaller sequence number is done.
+pub struct SequenceRange<'a> {
+    factory: &'a SessionFactory,
+    first: u64,
+    end: u64,
+    done: bool,
+}
+
+impl SequenceRange<'_> {
+    pub fn first(&self) -> u64 {
+        self.first
+    }
+
+    /// Blocks until every write before the range is published, the writes waiting for their
+    /// turn reach the memtable one at a time, in the order of their sequences.
+    pub fn wait_turn(&self) -> Result<()> {
+        self.factory.wait_published(self.first)
+    }
+
+    /// Publishes the range, then blocks until the writes before it are published too, so the
+    /// write is visible once it returns.
+    pub fn publish(mut self) -> Result<()> {
+        self.done = true;
+        self.factory.publish(self.first, self.end);
+        self.factory.wait_published(self.end)
+    }
+
+    /// Leaves the range unpublished for a write that reached the memtable in part. Nothing is
+    /// published past it anymore, the writes waiting for it and the later ones fail.
+    pub fn fail(mut self) {
+        self.done = true;
+        self.factory.stop();
+    }
+}
+
+impl Drop for SequenceRange<'_> {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+
+        // a panic may have left the write in the memtable in part, like `fail`
+        if panicking() {
+            self.factory.stop();
+        } else {
+            // nothing of the write reached the memtable, later writes are not held back by it
+            self.factory.publish(self.first, self.end);
+        }
+    }
+}
+
 #[allow(dead_code)]
 impl SessionFactory {
     pub fn new(sequence: u64) -> Self {
         SessionFactory {
             sequence: AtomicU64::new(sequence),
-            in_use_sequence: RwLock::new(HashSet::new()),
+            published: AtomicU64::new(sequence),
+            completed: Mutex::new(BTreeMap::new()),
+            published_signal: Condvar::new(),
+            stopped: AtomicBool::new(false),
+            in_use_sequence: RwLock::new(HashMap::new()),
         }
     }
 
     pub fn get_session<C: Comparator>(&self, core: Arc<DBCore<C>>) -> Session<C> {
         let mut in_use_set = self.in_use_sequence.write().unwrap();
-        let sequence = self.sequence.fetch_add(1, Ordering::SeqCst);
-        in_use_set.insert(sequence);
+        let sequence = self.last_sequence();
+        *in_use_set.entry(sequence).or_insert(0) += 1;
 
         Session::new(sequence, core)
     }
 
+    pub fn next_sequences(&self, count: u64) -> SequenceRange<'_> {
+        let first = self.sequence.fetch_add(count, Ordering::SeqCst);
+
+        SequenceRange {
+            factory: self,
+            first,
+            end: first + count,
+            done: false,
+        }
+    }
+
+    fn publish(&self, first: u64, end: u64) {
+        let mut completed = self.completed.lock().unwrap();
+        completed.insert(first, end);
+
+        let mut published = self.published.load(Ordering::SeqCst);
+
+        while let Some(end) = completed.remove(&published) {
+            published = end;
+        }
+
+        self.published.store(published, Ordering::SeqCst);
+        self.published_signal.notify_all();
+    }
+
+    fn wait_published(&self, end: u64) -> Result<()> {
+        let completed = self.completed.lock().unwrap();
+        let _completed = self
+            .published_signal
+            .wait_while(completed, |_| {
+                self.last_sequence() < end && !self.stopped.load(Ordering::SeqCst)
+            })
+            .unwrap();
+
+        if self.last_sequence() >= end {
+            Ok(())
+        } else {
+            Err(Error::WritesStopped)
+        }
+    }
+
+    fn stop(&self) {
+        let _completed = self.completed.lock().unwrap();
+        self.stopped.store(true, Ordering::SeqCst);
+        self.published_signal.notify_all();
+    }
+
+    /// Set once a write failed after reaching the memtable, no write is accepted afterwards.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+
+    /// Blocks until the writes are published past `seen` or `timeout` elapses.
+    pub fn wait_for_publish(&self, seen: u64, timeout: Duration) {
+        let completed = self.completed.lock().unwrap();
+        let _ = self
+            .published_signal
+            .wait_timeout_while(completed, timeout, |_| self.last_sequence() == seen)
+            .unwrap();
+    }
+
+    // every write below it is visible
+    pub fn last_sequence(&self) -> u64 {
+        self.published.load(Ordering::SeqCst)
+    }
+
     pub fn drop_sequence(&self, sequence: u64) {
         let mut in_use_set = self.in_use_sequence.write().unwrap();
 
-        in_use_set.remove(&sequence);
+        if let Some(count) = in_use_set.get_mut(&sequence) {
+            *count -= 1;
+
+            if *count == 0 {
+                in_use_set.remove(&sequence);
+            }
+        }
     }
 
     pub fn sequence_in_use(&self, sequence: u64) -> bool {
         let in_use_set = self.in_use_sequence.read().unwrap();
 
-        in_use_set.contains(&sequence)
+        in_use_set.contains_key(&sequence)
+    }
+
+    pub fn oldest_sequence_in_use(&self) -> Option<u64> {
+        let in_use_set = self.in_use_sequence.read().unwrap();
+
+        in_use_set.keys().min().cloned()
+    }
+
+    pub fn sequences_in_use(&self) -> Vec<u64> {
+        let in_use_set = self.in_use_sequence.read().unwrap();
+        let mut sequences: Vec<u64> = in_use_set.keys().cloned().collect();
+        sequences.sort_unstable();
+
+        sequences
     }
 }
+
+#[test]
+fn test_publish_sequence_range() {
+    use std::thread::{scope, sleep};
+
+    let factory = SessionFactory::new(0);
+    let first = factory.next_sequences(2);
+    let second = factory.next_sequences(1);
+
+    // a write is not visible before the writes preceding it
+    scope(|scope| {
+        let waiting = scope.spawn(|| second.publish());
+        sleep(Duration::from_millis(50));
+        assert!(!waiting.is_finished());
+        assert_eq!(factory.last_sequence(), 0);
+
+        drop(first);
+        assert!(waiting.join().unwrap().is_ok());
+    });
+    assert_eq!(factory.last_sequence(), 3);
+
+    let failed = factory.next_sequences(1);
+    let next = factory.next_sequences(1);
+
+    scope(|scope| {
+        let waiting = scope.spawn(|| next.publish());
+        failed.fail();
+        assert!(matches!(waiting.join().unwrap(), Err(Error::WritesStopped)));
+    });
+    assert_eq!(factory.last_sequence(), 3);
+    assert!(factory.is_stopped());
+}
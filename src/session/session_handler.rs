@@ -1,5 +1,7 @@
 use crate::core::DBCore;
+use crate::error::Result;
 use crate::Comparator;
+use bytes::Bytes;
 use std::sync::Arc;
 
 pub struct Session<C: Comparator> {
@@ -14,6 +16,11 @@ impl<C: Comparator> Session<C> {
             core,
         }
     }
+
+    /// Reads `key` as it was when the session was created.
+    pub fn get(&self, key: &Bytes) -> Result<Option<Bytes>> {
+        self.core.get(key, self.sequence_number)
+    }
 }
 
 impl<C: Comparator> Drop for Session<C> {
@@ -1,47 +1,74 @@
 use crate::skip_list::node::Node;
+use crate::skip_list::{shard_count, thread_index};
 use std::mem::align_of;
 use std::ptr::null_mut;
-use std::sync::RwLock;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::sync::Mutex;
 
-struct ArenaCore {
-    blocks: Vec<Box<[u8]>>,
-    current_block: usize,
-    current_block_remaining: usize,
-    memory_usage: usize,
+struct Block {
+    // owns the memory `ptr` points to
+    _data: Box<[u8]>,
     ptr: *mut u8,
+    size: usize,
+    offset: AtomicUsize,
 }
 
+impl Block {
+    fn new(size: usize) -> Box<Block> {
+        let mut data = vec![0_u8; size].into_boxed_slice();
+
+        Box::new(Block {
+            ptr: data.as_mut_ptr(),
+            _data: data,
+            size,
+            offset: AtomicUsize::new(0),
+        })
+    }
+
+    // returns `None` once the block is full, the offset is left past its end
+    fn allocate(&self, size: usize) -> Option<*mut u8> {
+        let offset = self.offset.fetch_add(size, Ordering::Relaxed);
+
+        if offset + size <= self.size {
+            Some(unsafe { self.ptr.add(offset) })
+        } else {
+            None
+        }
+    }
+}
+
+/// A bump allocator shared by the writers of a skip list. Each thread allocates from the
+/// current block of its shard with a single atomic add, the lock is only taken to add a block.
 #[allow(dead_code)]
 pub struct Arena {
-    core: RwLock<ArenaCore>,
+    shards: Box<[AtomicPtr<Block>]>,
+    // boxed so the blocks the shards point to never move
+    #[allow(clippy::vec_box)]
+    blocks: Mutex<Vec<Box<Block>>>,
+    memory_usage: AtomicUsize,
     align: usize,
     block_size: usize,
 }
 
 unsafe impl Send for Arena {}
+unsafe impl Sync for Arena {}
 
 #[allow(dead_code)]
 impl Arena {
     pub fn new(block_size: usize) -> Arena {
         Arena {
-            core: RwLock::new(ArenaCore {
-                blocks: vec![],
-                current_block: 0,
-                current_block_remaining: 0,
-                memory_usage: 0,
-                ptr: null_mut(),
-            }),
+            shards: (0..shard_count())
+                .map(|_| AtomicPtr::new(null_mut()))
+                .collect(),
+            blocks: Mutex::new(vec![]),
+            memory_usage: AtomicUsize::new(0),
             align: align_of::<Node>(),
             block_size,
         }
     }
 
-    fn create_block(size: usize) -> Box<[u8]> {
-        vec![0 as u8; size].into_boxed_slice()
-    }
-
     pub fn memory_usage(&self) -> usize {
-        self.core.read().unwrap().memory_usage
+        self.memory_usage.load(Ordering::Relaxed)
     }
 
     pub fn allocate(&self, mut size: usize) -> *mut u8 {
@@ -52,34 +79,42 @@ impl Arena {
 
         size += slop;
 
-        let mut core = self.core.write().unwrap();
+        self.memory_usage.fetch_add(size, Ordering::Relaxed);
 
-        core.memory_usage += size;
+        if size > self.block_size / 4 {
+            let block = Block::new(size);
+            let result = block.ptr;
+            self.blocks.lock().unwrap().push(block);
 
-        if size <= core.current_block_remaining {
-            let result = core.ptr;
-            core.ptr = unsafe { core.ptr.add(size) };
-            core.current_block_remaining -= size;
+            return result;
+        }
 
-            result
-        } else if size > self.block_size / 4 {
-            let block = Self::create_block(size);
+        let shard = &self.shards[thread_index() % self.shards.len()];
 
-            core.blocks.push(block);
+        loop {
+            let current = shard.load(Ordering::Acquire);
 
-            core.blocks.last_mut().unwrap().as_mut_ptr()
-        } else {
-            let block = Self::create_block(self.block_size);
+            if let Some(result) = unsafe { current.as_ref() }.and_then(|block| block.allocate(size))
+            {
+                return result;
+            }
 
-            core.blocks.push(block);
+            let mut blocks = self.blocks.lock().unwrap();
 
-            let result = core.blocks.last_mut().unwrap().as_mut_ptr();
+            // another thread of the shard added a block in the meantime
+            if shard.load(Ordering::Acquire) != current {
+                continue;
+            }
 
-            core.ptr = unsafe { result.clone().add(size) };
-            core.current_block = core.blocks.len() - 1;
-            core.current_block_remaining = self.block_size - size;
+            let block = Block::new(self.block_size);
+            let result = block.allocate(size).unwrap();
+            shard.store(
+                block.as_ref() as *const Block as *mut Block,
+                Ordering::Release,
+            );
+            blocks.push(block);
 
-            result
+            return result;
         }
     }
 }
@@ -173,6 +173,23 @@ impl<'a, C: Comparator> SkipListInternalVisitor<'a, C> {
         self.set_current(result);
         self.set_zero_level();
     }
+
+    // moves to the last node whose key is less than `key`
+    pub fn seek_predecessor(&mut self, key: &[u8]) {
+        assert!(self.is_head());
+
+        loop {
+            match self.compare_and_get_next(key) {
+                (Ordering::Less, Some(next_ptr)) => {
+                    let level = self.current_level();
+                    self.set_current(next_ptr);
+                    self.set_level(level);
+                }
+                _ if self.current_level() == 0 => break,
+                _ => self.reduce_level(),
+            }
+        }
+    }
 }
 
 pub struct SkipListIterator<'a, C: Comparator> {
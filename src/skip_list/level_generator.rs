@@ -1,4 +1,8 @@
-use rand::random;
+use crate::skip_list::MAX_HEIGHT;
+use rand::rngs::StdRng;
+use rand::{random, Rng, SeedableRng};
+use std::cell::Cell;
+use std::sync::Mutex;
 
 pub trait LevelGenerator: Send {
     fn max_level(&self) -> usize;
@@ -6,6 +10,29 @@ pub trait LevelGenerator: Send {
     fn generate_level(&self) -> usize;
 }
 
+/// The max level that leaves about one node on the top level of a skip list holding
+/// `expected_entries` entries, with the probability `p` for a node to reach the next level.
+pub fn max_level_for_entries(expected_entries: usize, p: f32) -> usize {
+    if expected_entries <= 1 || p <= 0.0 || p >= 1.0 {
+        return 0;
+    }
+
+    let levels = (expected_entries as f64).ln() / (1.0 / p as f64).ln();
+
+    (levels.ceil() as usize).min(MAX_HEIGHT - 1)
+}
+
+// goes up one level for every draw not greater than `p`
+fn draw_level(max_level: usize, p: f32, mut draw: impl FnMut() -> f32) -> usize {
+    let mut level = 0;
+
+    while level < max_level && draw() <= p {
+        level += 1;
+    }
+
+    level
+}
+
 pub struct RandomLevelGenerator {
     max_level: usize,
     p: f32,
@@ -23,19 +50,128 @@ impl LevelGenerator for RandomLevelGenerator {
         self.max_level
     }
 
+    fn generate_level(&self) -> usize {
+        draw_level(self.max_level, self.p, random::<f32>)
+    }
+}
+
+unsafe impl Send for RandomLevelGenerator {}
+
+/// Draws the levels from a seeded generator, the same seed and the same inserts give skip lists
+/// of the same shape. The threads inserting concurrently share the generator.
+pub struct SeededLevelGenerator {
+    max_level: usize,
+    p: f32,
+    rng: Mutex<StdRng>,
+}
+
+#[allow(dead_code)]
+impl SeededLevelGenerator {
+    pub fn new(max_level: usize, p: f32, seed: u64) -> SeededLevelGenerator {
+        SeededLevelGenerator {
+            max_level,
+            p,
+            rng: Mutex::new(StdRng::seed_from_u64(seed)),
+        }
+    }
+}
+
+impl LevelGenerator for SeededLevelGenerator {
+    fn max_level(&self) -> usize {
+        self.max_level
+    }
+
+    fn generate_level(&self) -> usize {
+        let mut rng = self.rng.lock().unwrap();
+
+        draw_level(self.max_level, self.p, || rng.gen::<f32>())
+    }
+}
+
+thread_local! {
+    // never zero, which xorshift would keep forever
+    static XORSHIFT_STATE: Cell<u64> = Cell::new(random::<u64>() | 1);
+}
+
+/// Draws the levels from a xorshift generator whose state is kept per thread, so the inserting
+/// threads neither share nor lock anything.
+pub struct XorShiftLevelGenerator {
+    max_level: usize,
+    // a draw of the top 24 bits below it goes up one level
+    threshold: u32,
+}
+
+#[allow(dead_code)]
+impl XorShiftLevelGenerator {
+    pub fn new(max_level: usize, p: f32) -> XorShiftLevelGenerator {
+        XorShiftLevelGenerator {
+            max_level,
+            threshold: (p.clamp(0.0, 1.0) as f64 * (1u64 << 24) as f64) as u32,
+        }
+    }
+
+    fn next_u24() -> u32 {
+        XORSHIFT_STATE.with(|state| {
+            let mut x = state.get();
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            state.set(x);
+
+            (x >> 40) as u32
+        })
+    }
+}
+
+impl LevelGenerator for XorShiftLevelGenerator {
+    fn max_level(&self) -> usize {
+        self.max_level
+    }
+
     fn generate_level(&self) -> usize {
         let mut level = 0;
 
-        while level < self.max_level() {
-            if random::<f32>() <= self.p {
-                level += 1;
-            } else {
-                break;
-            }
+        while level < self.max_level && Self::next_u24() < self.threshold {
+            level += 1;
         }
 
         level
     }
 }
 
-unsafe impl Send for RandomLevelGenerator {}
+#[test]
+fn test_seeded_level_generator() {
+    let levels = |seed: u64| {
+        let generator = SeededLevelGenerator::new(10, 0.25, seed);
+
+        (0..1000)
+            .map(|_| generator.generate_level())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(levels(7), levels(7));
+    assert_ne!(levels(7), levels(8));
+}
+
+#[test]
+fn test_xorshift_level_generator() {
+    let generator = XorShiftLevelGenerator::new(5, 0.25);
+    let levels = (0..100000)
+        .map(|_| generator.generate_level())
+        .collect::<Vec<_>>();
+
+    assert!(levels.iter().all(|level| *level <= 5));
+
+    // about a quarter of the nodes reach the level above
+    let above = levels.iter().filter(|level| **level >= 1).count();
+    assert!(above > 20000 && above < 30000);
+}
+
+#[test]
+fn test_max_level_for_entries() {
+    assert_eq!(max_level_for_entries(0, 0.25), 0);
+    assert_eq!(max_level_for_entries(4, 0.25), 1);
+    assert_eq!(max_level_for_entries(500, 0.1), 3);
+    assert_eq!(max_level_for_entries(1_000_000, 0.25), 10);
+    assert_eq!(max_level_for_entries(usize::MAX, 0.5), MAX_HEIGHT - 1);
+}
@@ -2,6 +2,7 @@ use crate::skip_list::arena::Arena;
 use crate::skip_list::iter::{SkipListInternalVisitor, SkipListIterator, SkipListVisitor};
 use crate::skip_list::level_generator::LevelGenerator;
 use crate::skip_list::node::Node;
+use crate::skip_list::{shard_count, thread_index, RangeEstimate};
 use crate::Comparator;
 use bytes::Bytes;
 use std::cmp::Ordering;
@@ -9,6 +10,13 @@ use std::marker::PhantomData;
 use std::ptr::{null_mut, NonNull};
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::{AtomicPtr, Ordering as AtomicOrdering};
+use std::sync::Mutex;
+
+// the nodes around the last key a thread inserted on every level, from the bottom one
+type Splice = Vec<(*mut Node, *mut Node)>;
+
+// the entries of a level an estimate is taken from, unless the bottom level is reached
+const MIN_ESTIMATE_SAMPLES: usize = 16;
 
 pub struct SkipList<C: Comparator> {
     entry: AtomicPtr<Node>,
@@ -16,9 +24,13 @@ pub struct SkipList<C: Comparator> {
     len: AtomicUsize,
     level_generator: Box<dyn LevelGenerator>,
     height: AtomicUsize,
+    // the number of nodes linked on every level
+    level_counts: Box<[AtomicUsize]>,
+    splices: Box<[Mutex<Splice>]>,
     _key_comparator: PhantomData<C>,
 }
 
+unsafe impl<C: Comparator> Send for SkipList<C> {}
 unsafe impl<C: Comparator> Sync for SkipList<C> {}
 
 #[allow(dead_code)]
@@ -32,19 +44,66 @@ impl<C: Comparator> SkipList<C> {
             entry: AtomicPtr::new(entry),
             arena,
             len: AtomicUsize::new(0),
-            level_generator,
             height: AtomicUsize::new(0),
+            level_counts: (0..=level_generator.max_level())
+                .map(|_| AtomicUsize::new(0))
+                .collect(),
+            splices: (0..shard_count()).map(|_| Mutex::new(vec![])).collect(),
+            level_generator,
             _key_comparator: Default::default(),
         }
     }
 
     pub fn insert(&self, key: Bytes, value: Bytes) {
-        let mut prev_next = self.find_position(key.as_ref());
+        let prev_next = self.find_position(key.as_ref());
+
+        self.link(key, value, prev_next);
+    }
+
+    /// Inserts like `insert`, but starts the search from the position of the last key the
+    /// thread inserted, which saves most of the search when the keys are inserted in order.
+    pub fn insert_with_hint(&self, key: Bytes, value: Bytes) {
+        // the slot is shared with another thread, which is using it
+        let mut splice = match self.splices[thread_index() % self.splices.len()].try_lock() {
+            Ok(splice) => splice,
+            Err(_) => return self.insert(key, value),
+        };
+
+        let prev_next = self.find_position_with_splice(key.as_ref(), &splice);
+
+        *splice = match self.link(key, value, prev_next.clone()) {
+            Some(node) => {
+                let node_level = unsafe { node.as_ref().unwrap() }.height();
+
+                prev_next
+                    .into_iter()
+                    .enumerate()
+                    .map(|(level, (prev, next))| {
+                        if level <= node_level {
+                            (node, next)
+                        } else {
+                            (prev, next)
+                        }
+                    })
+                    .collect()
+            }
+            // a duplicate key leaves its node on both sides, which would be taken for another
+            // duplicate by the next insert
+            None => vec![],
+        };
+    }
 
+    // links a node between the nodes found by a search, returns `None` for a duplicate key
+    fn link(
+        &self,
+        key: Bytes,
+        value: Bytes,
+        mut prev_next: Vec<(*mut Node, *mut Node)>,
+    ) -> Option<*mut Node> {
         for i in prev_next.iter() {
             // duplicate key
             if i.0 == i.1 {
-                return;
+                return None;
             }
         }
 
@@ -85,7 +144,7 @@ impl<C: Comparator> SkipList<C> {
                         next = result.1;
 
                         if prev == next {
-                            return;
+                            return None;
                         }
                     }
                 }
@@ -112,6 +171,29 @@ impl<C: Comparator> SkipList<C> {
             }
         }
         self.len.fetch_add(1, AtomicOrdering::SeqCst);
+
+        for count in self.level_counts[..=node_level].iter() {
+            count.fetch_add(1, AtomicOrdering::SeqCst);
+        }
+
+        Some(node_ptr)
+    }
+
+    /// Lets `update` modify the value of the entry whose key is equal to `key` in place, like
+    /// `Node::update_value`. `false` when the key is not found or the value is left unchanged. The readers of the value must be kept out by the caller, the
+    /// searches and `estimate_range` only read its length.
+    pub fn update_value(
+        &self,
+        key: &[u8],
+        update: &mut dyn FnMut(&mut [u8], usize) -> Option<usize>,
+    ) -> bool {
+        let mut visitor = self.internal_visitor();
+        visitor.seek(key, false);
+
+        match visitor.current_ptr() {
+            Some(ptr) => unsafe { ptr.as_ref() }.update_value(update),
+            None => false,
+        }
     }
 
     pub fn len(&self) -> usize {
@@ -122,6 +204,53 @@ impl<C: Comparator> SkipList<C> {
         self.arena.memory_usage()
     }
 
+    /// Estimates the entries whose keys are in `[begin, end)`. The levels are searched from the
+    /// top, and the entries in the range are counted on the first level holding enough of them,
+    /// then scaled by the share of all the entries that level holds. Only the bottom level gives
+    /// an exact result.
+    pub fn estimate_range(&self, begin: &[u8], end: &[u8]) -> RangeEstimate {
+        let mut prev = self.entry.load(AtomicOrdering::SeqCst);
+
+        for level in (0..=self.height()).rev() {
+            let (found, next) = self.find_position_for_level(prev, begin, level);
+
+            // the node equal to `begin` is counted, the search below starts before it
+            if found != next {
+                prev = found;
+            }
+
+            let mut estimate = RangeEstimate::default();
+            let mut node = next;
+
+            while let Some(current) = unsafe { node.as_ref() } {
+                let key = current.key().unwrap();
+
+                if C::compare(key, end) != Ordering::Less {
+                    break;
+                }
+
+                estimate.count += 1;
+                estimate.size += key.len() + current.value().unwrap().len();
+                node = current.next(level);
+            }
+
+            if level == 0 {
+                return estimate;
+            }
+
+            if estimate.count >= MIN_ESTIMATE_SAMPLES {
+                let level_count = self.level_counts[level].load(AtomicOrdering::SeqCst).max(1);
+
+                return RangeEstimate {
+                    count: estimate.count * self.len() / level_count,
+                    size: estimate.size * self.len() / level_count,
+                };
+            }
+        }
+
+        RangeEstimate::default()
+    }
+
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
@@ -130,6 +259,14 @@ impl<C: Comparator> SkipList<C> {
         SkipListIterator::from(self.internal_visitor())
     }
 
+    /// Iterates from the first entry whose key is greater than or equal to `key`.
+    pub fn iter_from(&self, key: &[u8]) -> SkipListIterator<C> {
+        let mut visitor = self.internal_visitor();
+        visitor.seek_predecessor(key);
+
+        SkipListIterator::from(visitor)
+    }
+
     pub fn visitor(&self) -> SkipListVisitor<C> {
         SkipListVisitor::new(self, self.internal_visitor())
     }
@@ -165,6 +302,52 @@ impl<C: Comparator> SkipList<C> {
         result
     }
 
+    // whether `key` falls between the nodes of a level of a splice
+    fn brackets(prev: *mut Node, next: *mut Node, key: &[u8]) -> bool {
+        let prev = unsafe { prev.as_ref().unwrap() };
+        let next = unsafe { next.as_ref() };
+
+        prev.key()
+            .is_none_or(|prev| C::compare(prev, key) == Ordering::Less)
+            && next.is_none_or(|next| C::compare(key, next.key().unwrap()) == Ordering::Less)
+    }
+
+    // searches the levels below the lowest one of the splice bracketing `key`, the levels
+    // above keep the nodes of the splice
+    fn find_position_with_splice(
+        &self,
+        key: &[u8],
+        splice: &Splice,
+    ) -> Vec<(*mut Node, *mut Node)> {
+        let height = self.height();
+
+        let start = match splice
+            .iter()
+            .take(height + 1)
+            .position(|(prev, next)| Self::brackets(*prev, *next, key))
+        {
+            Some(start) => start,
+            None => return self.find_position(key),
+        };
+
+        let entry = self.entry.load(AtomicOrdering::SeqCst);
+        let mut result = splice.clone();
+        // the list has grown since the splice was taken, the new levels are searched from the
+        // head when linking
+        result.resize(height + 1, (entry, null_mut()));
+        result.truncate(height + 1);
+
+        let mut prev = splice[start].0;
+
+        for level in (0..=start).rev() {
+            let item = self.find_position_for_level(prev, key, level);
+            prev = item.0;
+            result[level] = item;
+        }
+
+        result
+    }
+
     fn find_position_for_level(
         &self,
         start: *mut Node,
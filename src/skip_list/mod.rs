@@ -6,8 +6,48 @@ mod node;
 #[cfg(test)]
 mod tests;
 
-pub use iter::{SkipListIterator, SkipListVisitor};
-pub use level_generator::{LevelGenerator, RandomLevelGenerator};
+pub use level_generator::{
+    max_level_for_entries, LevelGenerator, RandomLevelGenerator, SeededLevelGenerator,
+    XorShiftLevelGenerator,
+};
 pub use list::SkipList;
 
+use std::ops::Add;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::available_parallelism;
+
 const MAX_HEIGHT: usize = 20;
+
+/// The approximate number of entries in a key range, and the bytes of their keys and values.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct RangeEstimate {
+    pub count: usize,
+    pub size: usize,
+}
+
+impl Add for RangeEstimate {
+    type Output = RangeEstimate;
+
+    fn add(self, other: RangeEstimate) -> RangeEstimate {
+        RangeEstimate {
+            count: self.count + other.count,
+            size: self.size + other.size,
+        }
+    }
+}
+
+// the number of slots of the per-thread state, the threads share them when there are more
+fn shard_count() -> usize {
+    available_parallelism().map_or(1, |count| count.get())
+}
+
+// a number given to each thread on its first call
+fn thread_index() -> usize {
+    static NEXT_INDEX: AtomicUsize = AtomicUsize::new(0);
+
+    thread_local! {
+        static INDEX: usize = NEXT_INDEX.fetch_add(1, Ordering::Relaxed);
+    }
+
+    INDEX.with(|index| *index)
+}
@@ -5,7 +5,7 @@ use std::intrinsics::copy_nonoverlapping;
 use std::mem::size_of;
 use std::ptr::write_bytes;
 use std::ptr::{null, null_mut, slice_from_raw_parts, write};
-use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
 
 #[allow(dead_code)]
 #[derive(Debug)]
@@ -13,7 +13,11 @@ use std::sync::atomic::{AtomicPtr, Ordering};
 pub struct Node {
     key: *const u8,
     value: *const u8,
-    size: usize,
+    // the size of the key and the value, the value is resized by the in-place updates while the
+    // node is read
+    size: AtomicUsize,
+    // the room the value was first written to, the in-place updates resize it up to this
+    value_capacity: usize,
     height: usize,
     next: [AtomicPtr<Node>; MAX_HEIGHT],
 }
@@ -43,7 +47,8 @@ impl Node {
             copy_nonoverlapping(value.as_ptr(), value_ptr, value_size);
             write(&mut node.key, key_ptr);
             write(&mut node.value, value_ptr);
-            write(&mut node.size, data_size);
+            write(&mut node.size, AtomicUsize::new(data_size));
+            write(&mut node.value_capacity, value_size);
             write(&mut node.height, height);
             write_bytes(node.next.as_mut_ptr(), 0, height + 1);
 
@@ -63,7 +68,8 @@ impl Node {
 
             write(&mut node.key, null());
             write(&mut node.value, null());
-            write(&mut node.size, 0);
+            write(&mut node.size, AtomicUsize::new(0));
+            write(&mut node.value_capacity, 0);
             write(&mut node.height, height);
             write_bytes(node.next.as_mut_ptr(), 0, height + 1);
 
@@ -100,7 +106,7 @@ impl Node {
     }
 
     fn value_size(&self) -> usize {
-        self.size - self.key_size()
+        self.size.load(Ordering::SeqCst) - self.key_size()
     }
 
     pub fn key(&self) -> Option<&[u8]> {
@@ -127,6 +133,30 @@ impl Node {
         }
     }
 
+    /// Lets `update` modify the value in place and resizes it to the length `update` returns.
+    /// `update` gets the whole room the value was first written to and the current length of the
+    /// value, so a value shrunk by an update can grow back. The bytes are written through the
+    /// pointer into the arena, the node itself is shared with the readers: the ones reading the
+    /// value must be kept out by the caller, the others only see its length change.
+    pub fn update_value(&self, update: &mut dyn FnMut(&mut [u8], usize) -> Option<usize>) -> bool {
+        if self.is_head() {
+            return false;
+        }
+
+        let room =
+            unsafe { std::slice::from_raw_parts_mut(self.value as *mut u8, self.value_capacity) };
+
+        match update(room, self.value_size()) {
+            Some(len) => {
+                assert!(len <= self.value_capacity);
+
+                self.size.store(self.key_size() + len, Ordering::SeqCst);
+                true
+            }
+            None => false,
+        }
+    }
+
     pub fn height(&self) -> usize {
         self.height
     }
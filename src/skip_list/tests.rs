@@ -1,8 +1,10 @@
-use super::{RandomLevelGenerator, SkipList};
+use super::arena::Arena;
+use super::{RandomLevelGenerator, RangeEstimate, SkipList};
 use crate::helper::test::*;
 use crate::NumberComparator;
 use rand::random;
-use std::sync::Arc;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
 
 fn create_skip_list(max_level: usize) -> SkipList<NumberComparator<u32>> {
     let level_generator = RandomLevelGenerator::new(max_level, 0.1);
@@ -90,3 +92,123 @@ fn test_concurrent() {
         assert!(visitor.valid());
     }
 }
+
+#[test]
+fn test_concurrent_arena() {
+    let arena = Arc::new(Arena::new(4096));
+    let allocated = Arc::new(Mutex::new(vec![]));
+
+    let pool = threadpool::ThreadPool::new(16);
+
+    for thread in 0..64_usize {
+        let arena = arena.clone();
+        let allocated = allocated.clone();
+        pool.execute(move || {
+            let mut ranges = vec![];
+
+            for i in 0..1000 {
+                // a few allocations take a dedicated block
+                let size = if i % 100 == 0 { 2048 } else { 8 + i % 50 };
+                let ptr = arena.allocate(size);
+                unsafe { ptr.write_bytes(thread as u8, size) };
+                ranges.push((ptr as usize, size, thread as u8));
+            }
+
+            allocated.lock().unwrap().extend(ranges);
+        });
+    }
+
+    pool.join();
+
+    let mut ranges = allocated.lock().unwrap().clone();
+    ranges.sort();
+
+    assert_eq!(ranges.len(), 64000);
+    assert_eq!(
+        ranges
+            .iter()
+            .map(|(ptr, ..)| *ptr)
+            .collect::<HashSet<_>>()
+            .len(),
+        ranges.len()
+    );
+
+    for window in ranges.windows(2) {
+        assert!(window[0].0 + window[0].1 <= window[1].0);
+    }
+
+    for (ptr, size, thread) in ranges {
+        let bytes = unsafe { std::slice::from_raw_parts(ptr as *const u8, size) };
+        assert!(bytes.iter().all(|byte| *byte == thread));
+    }
+
+    assert!(arena.memory_usage() >= 64 * (10 * 2048 + 990 * 8));
+}
+
+#[test]
+fn test_insert_with_hint() {
+    let skip_list = Arc::new(create_skip_list(19));
+    let threads = 8;
+    let count = 20000;
+
+    let handles = (0..threads)
+        .map(|thread| {
+            let skip_list = skip_list.clone();
+            std::thread::spawn(move || {
+                // in order, interleaved with the keys of the other threads
+                for key in (thread..count).step_by(threads as usize) {
+                    skip_list.insert_with_hint(get_bytes(key), get_bytes(key * 2));
+                }
+
+                // out of order and already inserted
+                for _ in 0..1000 {
+                    let key = thread + random::<u32>() % (count / threads) * threads;
+                    skip_list.insert_with_hint(get_bytes(key), get_bytes(0));
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    assert_eq!(skip_list.len(), count as usize);
+    assert_eq!(
+        skip_list
+            .iter()
+            .map(|(key, value)| (get_u32(key), get_u32(value)))
+            .collect::<Vec<_>>(),
+        (0..count).map(|key| (key, key * 2)).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_estimate_range() {
+    let skip_list =
+        SkipList::<NumberComparator<u32>>::new(Box::new(RandomLevelGenerator::new(19, 0.25)), 4096);
+
+    for key in 0..100000 {
+        skip_list.insert(get_bytes(key), get_bytes(key));
+    }
+
+    let estimate = |begin: u32, end: u32| {
+        skip_list.estimate_range(get_bytes(begin).as_ref(), get_bytes(end).as_ref())
+    };
+
+    // a small range is counted on the bottom level
+    let small = estimate(500, 510);
+    assert_eq!(small.count, 10);
+    assert_eq!(small.size, 10 * 8);
+
+    assert_eq!(estimate(200000, 300000), RangeEstimate::default());
+    assert_eq!(estimate(600, 500), RangeEstimate::default());
+
+    for (begin, end) in [(0, 100000), (20000, 70000), (1000, 11000)] {
+        let large = estimate(begin, end);
+        let expected = (end - begin) as usize;
+
+        assert!(large.count > expected / 2 && large.count < expected * 2);
+        assert!(large.size > expected * 4 && large.size < expected * 16);
+    }
+}
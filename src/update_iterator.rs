@@ -0,0 +1,296 @@
+use crate::collector::LogPin;
+use crate::core::DBCore;
+use crate::error::{Error, Result};
+use crate::format::SequenceNumber;
+use crate::logger::{LogIterator, LogNumber, LogPosition};
+use crate::memtable::InternalKey;
+use crate::write_batch::WriteBatch;
+use crate::Comparator;
+use bytes::Bytes;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The entries of one write read from the logs, the first one has the sequence number
+/// `sequence`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BatchResult {
+    pub sequence: SequenceNumber,
+    pub batch: WriteBatch,
+}
+
+/// Reads the writes published since a sequence number from the logs, in the order they were
+/// logged. A write holding `since` is returned whole. The logs not read yet are pinned, so they
+/// are not collected under the iterator.
+///
+/// `next` returns `None` once it reaches a write not published yet or the live end of the logs,
+/// it can be called again to poll for the writes published since, or `wait_next` can be used to
+/// block until they come.
+pub struct UpdateIterator<C: Comparator> {
+    core: Arc<DBCore<C>>,
+    since: SequenceNumber,
+    // the front one is being read
+    pins: VecDeque<LogPin>,
+    iter: Option<LogIterator>,
+    position: Option<LogPosition>,
+    // whether the log was frozen when it was opened, in which case its end is final
+    frozen: bool,
+}
+
+impl<C: Comparator> UpdateIterator<C> {
+    pub fn new(core: Arc<DBCore<C>>, since: SequenceNumber) -> Result<Self> {
+        let pins = core
+            .pin_logs(|| core.log_manager().all_log_numbers())?
+            .into_iter()
+            .collect();
+
+        Ok(UpdateIterator {
+            core,
+            since,
+            pins,
+            iter: None,
+            position: None,
+            frozen: false,
+        })
+    }
+
+    /// Like `next`, but waits up to `timeout` for a write when the live end of the logs is
+    /// reached.
+    pub fn wait_next(&mut self, timeout: Duration) -> Option<Result<BatchResult>> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let seen = self.core.last_sequence();
+
+            if let Some(result) = self.next() {
+                return Some(result);
+            }
+
+            let now = Instant::now();
+
+            if now >= deadline {
+                return None;
+            }
+
+            self.core.wait_for_publish(seen, deadline - now);
+        }
+    }
+
+    fn open(&mut self, log_number: LogNumber) -> Result<()> {
+        let log_manager = self.core.log_manager();
+
+        self.frozen = log_number < log_manager.current_log_number();
+        self.iter = Some(log_manager.tail_iterator(log_number, self.position)?);
+
+        Ok(())
+    }
+
+    // moves to the log following the frozen one at the front
+    fn next_log(&mut self) -> Result<()> {
+        let finished = self.pins.pop_front().unwrap().log_number();
+        self.position = None;
+
+        if self.pins.is_empty() {
+            let log_manager = self.core.log_manager();
+            let next = self.core.pin_logs(|| {
+                log_manager
+                    .all_log_numbers()?
+                    .into_iter()
+                    .find(|log_number| *log_number > finished)
+                    .map(|log_number| vec![log_number])
+                    .ok_or(Error::MissingLog(finished))
+            })?;
+
+            self.pins.extend(next);
+        }
+
+        Ok(())
+    }
+
+    fn read(&mut self) -> Result<Option<BatchResult>> {
+        // the entries of the write being read, a write never spans two logs
+        let mut pending: Option<BatchResult> = None;
+
+        loop {
+            let log_number = self.pins.front().unwrap().log_number();
+
+            if self.iter.is_none() {
+                self.open(log_number)?;
+            }
+
+            let iter = self.iter.as_mut().unwrap();
+            let legacy = iter.is_legacy();
+
+            match iter.next() {
+                Some(wrapper) => {
+                    let wrapper = wrapper?;
+                    let record = wrapper.record();
+                    let (tag, key) = if legacy {
+                        InternalKey::split_legacy(record.key())
+                    } else {
+                        InternalKey::split(record.key())
+                    };
+
+                    // logged before it is published, it is read again from `position` once it is
+                    if tag.sequence_number >= self.core.last_sequence() {
+                        self.iter = None;
+                        return Ok(None);
+                    }
+
+                    let result = pending.get_or_insert_with(|| BatchResult {
+                        sequence: tag.sequence_number,
+                        batch: WriteBatch::new(),
+                    });
+                    let batch = &mut result.batch;
+
+                    if tag.is_value() {
+                        batch.put(
+                            Bytes::copy_from_slice(key),
+                            Bytes::copy_from_slice(record.value()),
+                        );
+                    } else if tag.is_range_deletion() {
+                        batch.delete_range(
+                            Bytes::copy_from_slice(key),
+                            Bytes::copy_from_slice(record.value()),
+                        );
+                    } else {
+                        batch.delete(Bytes::copy_from_slice(key));
+                    }
+
+                    if wrapper.continued() {
+                        continue;
+                    }
+
+                    self.position = iter.last_record_end();
+                    let result = pending.take().unwrap();
+
+                    if tag.sequence_number < self.since {
+                        continue;
+                    }
+
+                    return Ok(Some(result));
+                }
+                None => {
+                    self.iter = None;
+                    // the rest of a write cut by the end of a frozen log was lost by a crash,
+                    // the rest of one at the live end is read again from `position`
+                    pending = None;
+
+                    if self.frozen {
+                        self.next_log()?;
+                    } else if log_number == self.core.log_manager().current_log_number() {
+                        return Ok(None);
+                    }
+                    // otherwise it was frozen during the reading, its end is read once more
+                }
+            }
+        }
+    }
+}
+
+impl<C: Comparator> Iterator for UpdateIterator<C> {
+    type Item = Result<BatchResult>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read().transpose()
+    }
+}
+
+#[test]
+fn test_update_iterator() {
+    use crate::format::ValueTag;
+    use crate::helper::test::get_bytes;
+    use crate::options::{Options, WriteOptions};
+    use crate::{NumberComparator, ValueType};
+    use rand::random;
+    use std::env::temp_dir;
+    use std::thread::{sleep, spawn};
+
+    let mut dir = temp_dir();
+    dir.push(format!("komachi_test_updates_{}", random::<u16>()));
+
+    let core = Arc::new(DBCore::<NumberComparator<u32>>::new(Options::new(dir)).unwrap());
+    let write_options = WriteOptions::default();
+    let write = |key: u32, ty: ValueType| {
+        core.write(&write_options, get_bytes(key), get_bytes(key * 2), ty)
+            .unwrap()
+    };
+
+    for key in 0..100 {
+        write(key, ValueType::Value);
+    }
+
+    let mut updates = UpdateIterator::new(core.clone(), 10).unwrap();
+    let results = updates.by_ref().collect::<Result<Vec<_>>>().unwrap();
+
+    assert_eq!(results.len(), 90);
+    for (result, key) in results.iter().zip(10..100) {
+        assert_eq!(result.sequence, key as SequenceNumber);
+        assert_eq!(result.batch.entries()[0].key, get_bytes(key));
+        assert_eq!(result.batch.entries()[0].value, get_bytes(key * 2));
+    }
+
+    // the writes are followed across the rotation of the log
+    write(100, ValueType::TombStone);
+    core.renew_memtable().unwrap();
+    write(101, ValueType::Value);
+
+    let results = updates.by_ref().collect::<Result<Vec<_>>>().unwrap();
+    assert_eq!(
+        results
+            .iter()
+            .map(|result| (result.sequence, result.batch.entries()[0].ty))
+            .collect::<Vec<_>>(),
+        vec![(100, ValueType::TombStone), (101, ValueType::Value)]
+    );
+    assert!(updates.next().is_none());
+
+    let writer = {
+        let core = core.clone();
+
+        spawn(move || {
+            sleep(Duration::from_millis(50));
+            core.write(
+                &WriteOptions::default(),
+                get_bytes(102),
+                get_bytes(0),
+                ValueType::Value,
+            )
+            .unwrap();
+        })
+    };
+
+    let result = updates.wait_next(Duration::from_secs(10)).unwrap().unwrap();
+    assert_eq!(result.sequence, 102);
+    writer.join().unwrap();
+
+    assert!(updates.wait_next(Duration::from_millis(10)).is_none());
+
+    // a write is returned whole, from the one holding `since`
+    let mut batch = WriteBatch::new();
+    batch.put(get_bytes(103), get_bytes(0));
+    batch.delete(get_bytes(104));
+    batch.put(get_bytes(105), get_bytes(0));
+    core.write_batch(&write_options, batch).unwrap();
+
+    let results = UpdateIterator::new(core.clone(), 104)
+        .unwrap()
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].sequence, 103);
+    assert_eq!(results[0].batch.len(), 3);
+    assert_eq!(results[0].batch.entries()[1].ty, ValueType::TombStone);
+
+    // a write logged but not published yet is not returned
+    assert_eq!(updates.next().unwrap().unwrap(), results[0]);
+    let sequence = core.last_sequence();
+    let key = InternalKey::new(
+        get_bytes(106),
+        ValueTag::new(sequence, ValueType::Value).unwrap(),
+    );
+    core.log_manager()
+        .insert_records_with(false, || Ok(vec![(key.as_bytes(), Bytes::new())]))
+        .unwrap();
+    assert!(updates.next().is_none());
+}
@@ -0,0 +1,59 @@
+use crate::format::ValueType;
+use bytes::Bytes;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BatchEntry {
+    pub ty: ValueType,
+    pub key: Bytes,
+    pub value: Bytes,
+}
+
+/// Writes applied in order, with consecutive sequence numbers starting from the sequence of the
+/// batch.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct WriteBatch {
+    entries: Vec<BatchEntry>,
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        WriteBatch::default()
+    }
+
+    pub fn put(&mut self, key: Bytes, value: Bytes) {
+        self.entries.push(BatchEntry {
+            ty: ValueType::Value,
+            key,
+            value,
+        });
+    }
+
+    pub fn delete(&mut self, key: Bytes) {
+        self.entries.push(BatchEntry {
+            ty: ValueType::TombStone,
+            key,
+            value: Bytes::new(),
+        });
+    }
+
+    /// Deletes the keys in `[begin, end)`.
+    pub fn delete_range(&mut self, begin: Bytes, end: Bytes) {
+        self.entries.push(BatchEntry {
+            ty: ValueType::RangeDeletion,
+            key: begin,
+            value: end,
+        });
+    }
+
+    pub fn entries(&self) -> &[BatchEntry] {
+        self.entries.as_slice()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
@@ -0,0 +1,286 @@
+use crate::error::{Error, Result};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, Weak};
+
+// the memory a database charged, shared with the manager so it can pick the largest one
+struct ChargeState {
+    // the usage of the mutable and of the immutable memtables
+    charged: Mutex<(usize, usize)>,
+    mutable_memory_usage: AtomicUsize,
+    flush_requested: AtomicBool,
+    // the mutable memory counted as being flushed from the request to the end of the flush
+    scheduled: AtomicUsize,
+}
+
+/// A memory budget for the memtables of every database sharing it.
+///
+/// When the mutable memtables grow over their share of `buffer_size`, the manager requests a
+/// flush of the largest mutable memtable of all the databases, which its database does on its
+/// next write. The memory of a requested flush is no longer counted against the share, so the
+/// other databases are not flushed in the meantime.
+///
+/// A flush only moves memory to the immutable memtables. Their memory is released by their
+/// background compaction or when their database is closed, so when a stall limit is set, the
+/// writes past it wait until enough memory is released.
+pub struct WriteBufferManager {
+    buffer_size: usize,
+    stall_limit: Option<usize>,
+    memory_usage: AtomicUsize,
+    mutable_memory_usage: AtomicUsize,
+    scheduled: AtomicUsize,
+    charges: Mutex<Vec<Weak<ChargeState>>>,
+    requesting: Mutex<()>,
+    stall: Mutex<()>,
+    released: Condvar,
+}
+
+#[allow(dead_code)]
+impl WriteBufferManager {
+    pub fn new(buffer_size: usize) -> Self {
+        WriteBufferManager {
+            buffer_size,
+            stall_limit: None,
+            memory_usage: AtomicUsize::new(0),
+            mutable_memory_usage: AtomicUsize::new(0),
+            scheduled: AtomicUsize::new(0),
+            charges: Mutex::new(vec![]),
+            requesting: Mutex::new(()),
+            stall: Mutex::new(()),
+            released: Condvar::new(),
+        }
+    }
+
+    /// The writes wait while the memtables use more than `stall_limit` bytes.
+    pub fn set_stall_limit(&mut self, stall_limit: usize) {
+        self.stall_limit = Some(stall_limit);
+    }
+
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    /// The memory of all the memtables, mutable and immutable.
+    pub fn memory_usage(&self) -> usize {
+        self.memory_usage.load(Ordering::SeqCst)
+    }
+
+    pub fn mutable_memory_usage(&self) -> usize {
+        self.mutable_memory_usage.load(Ordering::SeqCst)
+    }
+
+    pub fn should_flush(&self) -> bool {
+        let mutable_memory_usage = self
+            .mutable_memory_usage()
+            .saturating_sub(self.scheduled.load(Ordering::SeqCst));
+
+        // the immutable memtables are left an eighth of the budget, or half of it once the
+        // budget is exhausted
+        mutable_memory_usage > self.buffer_size / 8 * 7
+            || (self.memory_usage() >= self.buffer_size
+                && mutable_memory_usage >= self.buffer_size / 2)
+    }
+
+    pub fn is_stalled(&self) -> bool {
+        self.stall_limit
+            .is_some_and(|stall_limit| self.memory_usage() > stall_limit)
+    }
+
+    // waits until the memory is back under the stall limit
+    fn wait_for_memory(&self, no_slowdown: bool) -> Result<()> {
+        if !self.is_stalled() {
+            return Ok(());
+        }
+
+        if no_slowdown {
+            return Err(Error::WriteStall);
+        }
+
+        let guard = self.stall.lock().unwrap();
+        drop(
+            self.released
+                .wait_while(guard, |_| self.is_stalled())
+                .unwrap(),
+        );
+
+        Ok(())
+    }
+
+    // requests the flush of the largest mutable memtable not requested yet
+    fn request_flush(&self) {
+        // a single writer picks the memtable, the others keep writing meanwhile
+        let _guard = match self.requesting.try_lock() {
+            Ok(guard) => guard,
+            Err(_) => return,
+        };
+
+        if !self.should_flush() {
+            return;
+        }
+
+        let charges = {
+            let mut charges = self.charges.lock().unwrap();
+            charges.retain(|charge| charge.strong_count() > 0);
+
+            charges
+                .iter()
+                .filter_map(|charge| charge.upgrade())
+                .collect::<Vec<_>>()
+        };
+
+        if let Some(largest) = charges
+            .iter()
+            .filter(|charge| charge.scheduled.load(Ordering::SeqCst) == 0)
+            .max_by_key(|charge| charge.mutable_memory_usage.load(Ordering::SeqCst))
+        {
+            let scheduled = largest.mutable_memory_usage.load(Ordering::SeqCst);
+
+            largest.scheduled.store(scheduled, Ordering::SeqCst);
+            self.scheduled.fetch_add(scheduled, Ordering::SeqCst);
+            largest.flush_requested.store(true, Ordering::SeqCst);
+        }
+    }
+
+    fn update(&self, old: (usize, usize), new: (usize, usize)) {
+        let (old_mutable, old_immutable) = old;
+        let (new_mutable, new_immutable) = new;
+
+        // added first, so a concurrent update of another database never makes them negative
+        self.mutable_memory_usage
+            .fetch_add(new_mutable, Ordering::SeqCst);
+        self.mutable_memory_usage
+            .fetch_sub(old_mutable, Ordering::SeqCst);
+        self.memory_usage
+            .fetch_add(new_mutable + new_immutable, Ordering::SeqCst);
+        self.memory_usage
+            .fetch_sub(old_mutable + old_immutable, Ordering::SeqCst);
+
+        if new_mutable + new_immutable < old_mutable + old_immutable {
+            let _guard = self.stall.lock().unwrap();
+            self.released.notify_all();
+        }
+    }
+}
+
+/// The memory a database charged to the manager, released when the database is dropped.
+pub(crate) struct WriteBufferCharge {
+    manager: Arc<WriteBufferManager>,
+    state: Arc<ChargeState>,
+}
+
+impl WriteBufferCharge {
+    pub fn new(manager: Arc<WriteBufferManager>) -> Self {
+        let state = Arc::new(ChargeState {
+            charged: Mutex::new((0, 0)),
+            mutable_memory_usage: AtomicUsize::new(0),
+            flush_requested: AtomicBool::new(false),
+            scheduled: AtomicUsize::new(0),
+        });
+
+        manager.charges.lock().unwrap().push(Arc::downgrade(&state));
+
+        WriteBufferCharge { manager, state }
+    }
+
+    pub fn update(&self, mutable_memory_usage: usize, immutable_memory_usage: usize) {
+        let mut charged = self.state.charged.lock().unwrap();
+        let new = (mutable_memory_usage, immutable_memory_usage);
+
+        self.manager.update(*charged, new);
+        self.state
+            .mutable_memory_usage
+            .store(mutable_memory_usage, Ordering::SeqCst);
+        *charged = new;
+    }
+
+    /// Called by the writers of the database before they write, runs `flush` when a flush of
+    /// the mutable memtable of the database is requested.
+    pub fn flush_if_requested(&self, flush: impl FnOnce() -> Result<()>) -> Result<()> {
+        if self.manager.should_flush() {
+            self.manager.request_flush();
+        }
+
+        if !self.state.flush_requested.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        let result = flush();
+        self.release_scheduled();
+
+        result
+    }
+
+    /// Called by the writers of the database after `flush_if_requested`, waits while the
+    /// memtables of all the databases are over the stall limit, or fails with
+    /// `Error::WriteStall` when `no_slowdown` is set.
+    pub fn stall_if_needed(&self, no_slowdown: bool) -> Result<()> {
+        self.manager.wait_for_memory(no_slowdown)
+    }
+
+    fn release_scheduled(&self) {
+        self.manager.scheduled.fetch_sub(
+            self.state.scheduled.swap(0, Ordering::SeqCst),
+            Ordering::SeqCst,
+        );
+    }
+}
+
+impl Drop for WriteBufferCharge {
+    fn drop(&mut self) {
+        self.release_scheduled();
+        self.update(0, 0);
+    }
+}
+
+#[test]
+fn test_write_buffer_charge() {
+    let manager = Arc::new(WriteBufferManager::new(1000));
+    let first = WriteBufferCharge::new(manager.clone());
+    let second = WriteBufferCharge::new(manager.clone());
+    let flushed = |charge: &WriteBufferCharge| {
+        let mut flushed = false;
+        charge
+            .flush_if_requested(|| {
+                flushed = true;
+                Ok(())
+            })
+            .unwrap();
+
+        flushed
+    };
+
+    first.update(300, 0);
+    second.update(400, 200);
+    assert_eq!(manager.memory_usage(), 900);
+    assert_eq!(manager.mutable_memory_usage(), 700);
+    assert!(!manager.should_flush());
+    assert!(!flushed(&first));
+
+    // over the budget with half of it mutable, the largest memtable is requested
+    first.update(100, 300);
+    second.update(600, 200);
+    assert!(manager.should_flush());
+    assert!(!flushed(&first));
+
+    // the requested memory no longer counts until it is flushed
+    assert!(!manager.should_flush());
+    first.update(200, 300);
+    assert!(!flushed(&first));
+
+    let mut flushes = 0;
+    second
+        .flush_if_requested(|| {
+            flushes += 1;
+            // not requested again while it is being flushed
+            assert!(!flushed(&second));
+            second.update(0, 800);
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(flushes, 1);
+    assert_eq!(manager.memory_usage(), 1300);
+    assert_eq!(manager.mutable_memory_usage(), 200);
+    assert!(!manager.should_flush());
+
+    drop(second);
+    assert_eq!(manager.memory_usage(), 500);
+}